@@ -7,8 +7,14 @@ pub enum Error {
     Io(io::Error),
     Fmt(fmt::Error),
     VarError(env::VarError),
-    NoChild,
-    StructureCollaps,
+    // 構文エラー（at は入力中のバイト位置）
+    UnexpectedToken { token: Option<String>, at: usize }, // None は入力の終わり
+    MissingRedirectTarget { at: usize },
+    UnterminatedQuote { quote: char, at: usize },
+    EmptyPipelineStage { at: usize },
+    Incomplete(Box<Error>), // 入力が途中で終わっている（続きの行があれば解釈できる）
+    Expansion(String),      // ${VAR:?msg} や不正な ${...}
+    NoMatch(String), // nomatch=error で何にも一致しなかったグロブ（そのコマンドだけ失敗させる）
 }
 
 impl fmt::Display for Error {
@@ -17,13 +23,56 @@ impl fmt::Display for Error {
             Error::Io(e) => write!(f, "IO error: {e}"),
             Error::Fmt(e) => write!(f, "Format error: {e}"),
             Error::VarError(e) => write!(f, "Variable error: {e}"),
-            Error::NoChild => write!(f, "no child to wait on"),
-            Error::StructureCollaps => write!(f, "Failed to parse tokens"),
+            Error::UnexpectedToken { token: Some(t), .. } => {
+                write!(f, "syntax error: unexpected token '{t}'")
+            }
+            Error::UnexpectedToken { token: None, .. } => {
+                write!(f, "syntax error: unexpected end of input")
+            }
+            Error::MissingRedirectTarget { .. } => {
+                write!(f, "syntax error: missing redirect target")
+            }
+            Error::UnterminatedQuote { quote, .. } => {
+                write!(f, "syntax error: unterminated quote {quote}")
+            }
+            Error::EmptyPipelineStage { .. } => write!(f, "syntax error: empty pipeline stage"),
+            Error::Incomplete(e) => write!(f, "{e}"),
+            Error::Expansion(msg) => write!(f, "{msg}"),
+            Error::NoMatch(pattern) => write!(f, "No matches for wildcard '{pattern}'"),
         }
     }
 }
 impl std::error::Error for Error {}
 
+impl Error {
+    /// 構文エラーなら入力中の位置
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Error::UnexpectedToken { at, .. }
+            | Error::MissingRedirectTarget { at }
+            | Error::UnterminatedQuote { at, .. }
+            | Error::EmptyPipelineStage { at } => Some(*at),
+            Error::Incomplete(e) => e.position(),
+            _ => None,
+        }
+    }
+
+    /// 位置を by だけずらす（コマンド置換の中身の位置を外側の入力での位置に直す）
+    pub fn shifted(mut self, by: usize) -> Self {
+        if let Error::Incomplete(e) = self {
+            return Error::Incomplete(Box::new(e.shifted(by)));
+        }
+        if let Error::UnexpectedToken { at, .. }
+        | Error::MissingRedirectTarget { at }
+        | Error::UnterminatedQuote { at, .. }
+        | Error::EmptyPipelineStage { at } = &mut self
+        {
+            *at += by;
+        }
+        self
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         Error::Io(e)
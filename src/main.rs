@@ -5,7 +5,8 @@ mod ui;
 
 use std::{
     collections::BTreeSet,
-    fs,
+    env, fs,
+    io::{self, IsTerminal},
     path::{MAIN_SEPARATOR, Path},
 };
 
@@ -14,17 +15,26 @@ use shell::Shell;
 use ui::{Action, Mode};
 
 use crate::{
-    pipeline::{execute, expand_aliases, parse, tokenize, tokens_to_string},
+    pipeline::{
+        execute, execute_argv, expand_aliases, has_pending_heredoc, line_col, parse, statements,
+        tokenize, tokens_to_string,
+    },
     ui::{
         clean_term, delete_printing, flush, init, print_candidates, print_command_line,
-        print_hat_c, print_newline, print_prompt, set_origin_term, set_raw_term, wait_actions,
+        print_error_caret, print_hat_c, print_newline, print_prompt, set_origin_term, set_raw_term,
+        wait_actions,
     },
 };
 
 fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    // 引数があるか、入力が端末でなければ行エディタを使わずに実行する
+    if args.len() > 1 || !io::stdin().is_terminal() {
+        std::process::exit(run_non_interactive(&args));
+    }
     init();
     print!("\x1b[5 q"); // カーソルをブロックから縦棒へ変更する。
-    let mut shell = Shell::new();
+    let mut shell = Shell::new(true);
     let mut buffer = String::new();
     let mut cursor = 0;
     let mut candidates = vec![];
@@ -33,13 +43,19 @@ fn main() -> Result<()> {
     set_raw_term();
     print_prompt();
     'finish: loop {
-        delete_printing(cursor);
+        // rc や入力したコマンドの中で exit された
+        if shell.exit_requested.is_some() {
+            break;
+        }
         print_command_line(&buffer, cursor, &shell.get_ghost(&buffer));
         if pre_action == Action::Tab {
-            print_candidates(&candidates, cursor, None, completion_fixed_len);
+            print_candidates(&candidates, &buffer, cursor, None, completion_fixed_len);
         }
         flush();
-        let Ok(actions) = wait_actions(&Mode::LineEdit, 20) else {
+        let actions = wait_actions(&Mode::LineEdit, 20);
+        // 表示した時点の buffer と cursor で消す（以下の処理は表示の先頭から始める）
+        delete_printing(&buffer, cursor);
+        let Ok(actions) = actions else {
             continue;
         };
         for action in actions {
@@ -53,9 +69,7 @@ fn main() -> Result<()> {
                     buffer.insert(cursor, ' ');
                     cursor += 1;
                 }
-                Action::Ctrl('c') => {
-                    reset(&mut buffer, &mut cursor, &mut shell);
-                }
+                Action::Ctrl('c') => reset(&mut buffer, &mut cursor, &mut shell),
                 Action::Ctrl('d') => {
                     if buffer.is_empty() {
                         break 'finish;
@@ -68,14 +82,21 @@ fn main() -> Result<()> {
                     buffer = shell.history.prev_r(&buffer);
                     cursor = buffer.len();
                 }
-                Action::PreCmd => {
-                    buffer = shell.history.prev_up(&buffer);
-                    cursor = buffer.len();
-                }
-                Action::NextCmd => {
-                    buffer = shell.history.next_down();
-                    cursor = buffer.len();
-                }
+                // 複数行の入力では、まず行の間を移動する
+                Action::PreCmd => match line_above(&buffer, cursor) {
+                    Some(c) => cursor = c,
+                    None => {
+                        buffer = shell.history.prev_up(&buffer);
+                        cursor = buffer.len();
+                    }
+                },
+                Action::NextCmd => match line_below(&buffer, cursor) {
+                    Some(c) => cursor = c,
+                    None => {
+                        buffer = shell.history.next_down();
+                        cursor = buffer.len();
+                    }
+                },
                 Action::Left => {
                     cursor = cursor.saturating_sub(1);
                 }
@@ -102,14 +123,20 @@ fn main() -> Result<()> {
                             complete(&mut buffer, &mut cursor, &mut shell);
                     }
                 }
-                Action::Home => cursor = 0,
-                Action::End => cursor = buffer.len(),
+                // 複数行の入力では今いる行の先頭・末尾
+                Action::Home => cursor = buffer[..cursor].rfind('\n').map_or(0, |p| p + 1),
+                Action::End => {
+                    cursor = buffer[cursor..]
+                        .find('\n')
+                        .map_or(buffer.len(), |p| cursor + p)
+                }
                 Action::Enter => {
-                    let pre_cursor = cursor;
                     expand_abbr(&mut buffer, &mut cursor, &shell);
-                    delete_printing(pre_cursor);
+                    // 入力の途中で押しても、全体を実行（または次の行へ）する
+                    cursor = buffer.len();
                     print_command_line(&buffer, cursor, "");
                     if buffer.is_empty() {
+                        print_job_notifications(&mut shell);
                         print_prompt();
                     } else {
                         run_pipeline(&mut shell, &mut buffer, &mut cursor)
@@ -138,18 +165,110 @@ fn main() -> Result<()> {
     }
 
     set_origin_term();
-    Ok(())
+    let code = shell.exit_requested.unwrap_or(shell.status);
+    drop(shell); // on_exit のコマンドを実行し、履歴を保存する
+    std::process::exit(code);
+}
+
+/// `my_shell -c 'cmd' [args...]` / `my_shell script [args...]` / `cmd | my_shell`。
+/// source と同じく 1 文ずつ解析して実行し、最後の文の終了コードを返す。
+/// 構文エラーの文は報告して飛ばし（終了コード 2）、残りの文は実行する
+fn run_non_interactive(args: &[String]) -> i32 {
+    let (name, input, params) = match args.get(1).map(String::as_str) {
+        Some("-c") => match args.get(2) {
+            Some(command) => ("-c", Ok(command.clone()), &args[3..]),
+            None => {
+                eprintln!("my_shell: -c: option requires an argument");
+                return 2;
+            }
+        },
+        None | Some("-") => (
+            "stdin",
+            io::read_to_string(io::stdin()),
+            args.get(2..).unwrap_or_default(),
+        ),
+        Some(opt) if opt.starts_with('-') => {
+            eprintln!("my_shell: unknown option '{}'", opt);
+            return 2;
+        }
+        Some(path) => (path, fs::read_to_string(path), &args[2..]),
+    };
+    let input = match input {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("my_shell: {}: {}", name, e);
+            return 127;
+        }
+    };
+
+    let mut shell = Shell::new(false);
+    // $0 は -c ならシェル自身、スクリプトならそのパス
+    if !matches!(name, "-c" | "stdin") {
+        shell.args[0] = name.to_string();
+    }
+    shell.args.extend_from_slice(params);
+
+    let tokens = tokenize(&input);
+    let mut code = 0;
+    for statement in statements(&tokens) {
+        if let Some(exit_code) = shell.exit_requested {
+            return exit_code;
+        }
+        code = match statement.map(|expr| execute(&expr, &mut shell)) {
+            Ok(Ok(code)) => code,
+            Ok(Err(e)) => {
+                eprintln!("my_shell: {}", e);
+                1
+            }
+            Err(e) => {
+                match e.position() {
+                    Some(at) => {
+                        let (line, col) = line_col(&input, at);
+                        eprintln!("my_shell: {}:{}:{}: {}", name, line, col, e);
+                    }
+                    None => eprintln!("my_shell: {}: {}", name, e),
+                }
+                2
+            }
+        };
+    }
+    shell.exit_requested.unwrap_or(code)
 }
 
 fn run_pipeline(shell: &mut Shell, buffer: &mut String, cursor: &mut usize) {
     let tokens = tokenize(buffer);
-    *buffer = tokens_to_string(&tokens);
+    // ヒアドキュメントの本文は続きの行で受け付ける
+    if has_pending_heredoc(&tokens) {
+        continue_line(buffer, cursor);
+        return;
+    }
+    let input = std::mem::replace(buffer, tokens_to_string(&tokens));
     let tokens = expand_aliases(tokens, shell);
     let expr = match parse(&tokens) {
         Ok(expr) => expr,
+        // `git log |` や閉じていないクォートは続きの行を待つ
+        Err(error::Error::Incomplete(_)) => {
+            *buffer = input;
+            continue_line(buffer, cursor);
+            return;
+        }
         Err(e) => {
             print_newline();
-            print!("{}", e);
+            match e.position() {
+                Some(at) => {
+                    let (line, column) = line_col(&input, at);
+                    let message = if input.contains('\n') {
+                        format!("line {line}: {e}")
+                    } else {
+                        e.to_string()
+                    };
+                    print_error_caret(line, column - 1, &message);
+                }
+                None => print!("{}", e),
+            }
+            // 修正できるよう入力したままの行に戻す
+            *buffer = input;
+            *cursor = buffer.len();
             print_newline();
             print_prompt();
             return;
@@ -158,13 +277,61 @@ fn run_pipeline(shell: &mut Shell, buffer: &mut String, cursor: &mut usize) {
     print_newline();
     set_origin_term();
     shell.history.push(buffer.clone());
-    let _ = execute(&expr, shell);
+    shell.variables.refresh_universal(); // 入力を待つ間に書き換えられていることもある
+    shell.status = match execute(&expr, shell) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    };
+    // 端末の設定と保存はループを抜けた main が行う
+    if shell.exit_requested.is_some() {
+        return;
+    }
     set_raw_term();
+    print_job_notifications(shell);
+    // ほかのシェルが set -U した値を次のコマンドで使えるようにする
+    shell.variables.refresh_universal();
     print_prompt();
     buffer.clear();
     *cursor = 0;
 }
 
+/// 入力が途中なので改行を足し、継続プロンプトの付いた次の行で続きを受け付ける。
+/// 画面には buffer がカーソルを末尾にして表示されている前提
+fn continue_line(buffer: &mut String, cursor: &mut usize) {
+    delete_printing(buffer, *cursor);
+    buffer.push('\n');
+    *cursor = buffer.len();
+}
+
+/// 複数行の入力でカーソルを 1 行上へ（桁はできるだけ保つ）。先頭の行なら None
+fn line_above(buffer: &str, cursor: usize) -> Option<usize> {
+    let start = buffer[..cursor].rfind('\n')?;
+    let column = cursor - start - 1;
+    let above = buffer[..start].rfind('\n').map_or(0, |p| p + 1);
+    Some(above + column.min(start - above))
+}
+
+/// 複数行の入力でカーソルを 1 行下へ。最後の行なら None
+fn line_below(buffer: &str, cursor: usize) -> Option<usize> {
+    let column = cursor - buffer[..cursor].rfind('\n').map_or(0, |p| p + 1);
+    let below = cursor + buffer[cursor..].find('\n')? + 1;
+    let end = buffer[below..]
+        .find('\n')
+        .map_or(buffer.len(), |p| below + p);
+    Some(below + column.min(end - below))
+}
+
+/// 前回のプロンプト以降に終了・停止したジョブを知らせる
+fn print_job_notifications(shell: &mut Shell) {
+    for line in shell.jobs.notifications() {
+        print!("{}", line);
+        print_newline();
+    }
+}
+
 fn delete_word(buffer: &mut String, cursor: &mut usize) {
     if buffer.is_empty() || *cursor == 0 {
         return;
@@ -189,6 +356,10 @@ fn expand_abbr(buffer: &mut String, cursor: &mut usize, shell: &Shell) -> bool {
     }
     let old_len = buffer.len();
     let tokens = tokenize(buffer);
+    // 書き直すとクォートが閉じたり行継続が消えたりする
+    if tokens.unclosed_quote.is_some() || tokens.line_continued {
+        return false;
+    }
     if let Some(expanded) = crate::pipeline::expand_abbr(tokens, shell) {
         *buffer = tokens_to_string(&expanded);
         let new_len = buffer.len();
@@ -208,7 +379,6 @@ fn complete_mode(
 ) {
     let mut index = 0;
     'finish: loop {
-        delete_printing(*cursor);
         let adder = &candidates[index][fixed_len..];
         let mut tmp_buffer = buffer.clone() + adder;
         let mut tmp_cursor = *cursor + adder.len();
@@ -217,9 +387,11 @@ fn complete_mode(
             tmp_cursor += 1;
         }
         print_command_line(&tmp_buffer, tmp_cursor, "");
-        let width = print_candidates(&candidates, tmp_cursor, Some(index), fixed_len);
+        let width = print_candidates(&candidates, &tmp_buffer, tmp_cursor, Some(index), fixed_len);
         flush();
-        let Ok(actions) = wait_actions(&Mode::Completion, 20) else {
+        let actions = wait_actions(&Mode::Completion, 20);
+        delete_printing(&tmp_buffer, tmp_cursor);
+        let Ok(actions) = actions else {
             continue;
         };
         for action in actions {
@@ -271,7 +443,9 @@ fn complete(buffer: &mut String, cursor: &mut usize, shell: &mut Shell) -> (Vec<
     let Ok(expr) = parse(&tokens) else {
         return (vec![], 0);
     };
-    let last_cmd = expr.last_cmd_expr();
+    let Some(last_cmd) = expr.last_cmd_expr() else {
+        return (vec![], 0);
+    };
     let cmd = last_cmd.cmd_name.concat_text(shell);
     let args: Vec<String> = last_cmd
         .args
@@ -303,7 +477,9 @@ fn complete(buffer: &mut String, cursor: &mut usize, shell: &mut Shell) -> (Vec<
                 let src = get_exes(&dir);
                 return complete_parts(src, &file, buffer, cursor);
             } else {
-                let src = shell.exe_list.command_candidates(&cmd);
+                let src = shell
+                    .exe_list
+                    .command_candidates(&cmd, shell.variables.get("PATH").unwrap_or_default());
                 return complete_parts(src, &cmd, buffer, cursor);
             }
         }
@@ -516,6 +692,8 @@ where
 
 fn reset(buffer: &mut String, cursor: &mut usize, shell: &mut Shell) {
     if !buffer.is_empty() {
+        // 表示は消してあるので、入力を出し直して末尾に ^C を付ける
+        print_command_line(buffer, buffer.len(), "");
         print_hat_c();
         print_newline();
         print_prompt();
@@ -0,0 +1,464 @@
+// 算術式の評価（`$(( ))` と math builtin）。整数同士は整数のまま、どちらかが小数なら小数で計算する
+use std::fmt;
+
+use super::parse::variable_value;
+use crate::shell::Shell;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{n}"),
+            Number::Float(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Float(x) => x,
+        }
+    }
+
+    fn is_true(self) -> bool {
+        self.as_f64() != 0.0
+    }
+
+    fn int(self, op: &str) -> Result<i64, String> {
+        match self {
+            Number::Int(n) => Ok(n),
+            Number::Float(_) => Err(format!("'{op}' needs integer operands")),
+        }
+    }
+}
+
+/// 式を評価する。エラーはメッセージのみ（呼び出し側で接頭辞を付ける）
+pub fn eval_arith(expr: &str, shell: &Shell) -> Result<Number, String> {
+    let tokens = lex(expr)?;
+    if tokens.is_empty() {
+        return Ok(Number::Int(0));
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let node = parser.expr(0)?;
+    if let Some(t) = parser.tokens.get(parser.pos) {
+        return Err(format!("unexpected '{}'", t.text()));
+    }
+    eval(&node, shell)
+}
+
+#[derive(Debug, Clone)]
+enum Tok {
+    Num(Number),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl Tok {
+    fn text(&self) -> String {
+        match self {
+            Tok::Num(n) => n.to_string(),
+            Tok::Ident(s) => s.clone(),
+            Tok::Op(op) => op.to_string(),
+            Tok::LParen => "(".to_string(),
+            Tok::RParen => ")".to_string(),
+            Tok::Comma => ",".to_string(),
+        }
+    }
+}
+
+// 長いものから照合する
+const OPS: &[&str] = &[
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~",
+];
+
+fn lex(s: &str) -> Result<Vec<Tok>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()))
+        {
+            let start = i;
+            if c == '0' && matches!(chars.get(i + 1), Some('x' | 'X')) {
+                i += 2;
+                while i < chars.len() && chars[i].is_ascii_hexdigit() {
+                    i += 1;
+                }
+                let text: String = chars[start + 2..i].iter().collect();
+                let n = i64::from_str_radix(&text, 16)
+                    .map_err(|_| format!("invalid number '0x{text}'"))?;
+                tokens.push(Tok::Num(Number::Int(n)));
+                continue;
+            }
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // 指数表記 1e3 / 2.5e-2
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && matches!(chars[j], '+' | '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = parse_number(&text).ok_or_else(|| format!("invalid number '{text}'"))?;
+            tokens.push(Tok::Num(n));
+        } else if c == '$' || c.is_alphabetic() || c == '_' {
+            // $x と x はどちらも変数
+            let start = if c == '$' { i + 1 } else { i };
+            i = start;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if i == start {
+                return Err("expected a variable name after '$'".to_string());
+            }
+            tokens.push(Tok::Ident(chars[start..i].iter().collect()));
+        } else if c == '(' {
+            tokens.push(Tok::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Tok::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Tok::Comma);
+            i += 1;
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) else {
+                return Err(format!("unexpected character '{c}'"));
+            };
+            tokens.push(Tok::Op(op));
+            i += op.chars().count();
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<Number> {
+    let text = text.trim();
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(Number::Int(n));
+    }
+    text.parse::<f64>().ok().map(Number::Float)
+}
+
+#[derive(Debug)]
+enum Node {
+    Num(Number),
+    Var(String),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+    Call(String, Vec<Node>),
+}
+
+/// 二項演算子の優先順位（大きいほど強く結合する）
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        "**" => 11,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    /// 優先順位法。`**` だけは右結合
+    fn expr(&mut self, min: u8) -> Result<Node, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Tok::Op(op)) = self.peek() {
+            let op = *op;
+            let Some(prec) = precedence(op) else {
+                break;
+            };
+            if prec < min {
+                break;
+            }
+            self.pos += 1;
+            let next_min = if op == "**" { prec } else { prec + 1 };
+            let rhs = self.expr(next_min)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if let Some(Tok::Op(op @ ("-" | "+" | "!" | "~"))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            return Ok(Node::Unary(op, Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Tok::Num(n)) => Ok(Node::Num(n)),
+            Some(Tok::Ident(name)) => {
+                if !matches!(self.peek(), Some(Tok::LParen)) {
+                    return Ok(Node::Var(name));
+                }
+                self.pos += 1;
+                let mut args = Vec::new();
+                if !matches!(self.peek(), Some(Tok::RParen)) {
+                    loop {
+                        args.push(self.expr(0)?);
+                        match self.next() {
+                            Some(Tok::Comma) => continue,
+                            Some(Tok::RParen) => break,
+                            _ => return Err(format!("missing ')' after arguments of {name}")),
+                        }
+                    }
+                } else {
+                    self.pos += 1;
+                }
+                Ok(Node::Call(name, args))
+            }
+            Some(Tok::LParen) => {
+                let node = self.expr(0)?;
+                match self.next() {
+                    Some(Tok::RParen) => Ok(node),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(t) => Err(format!("unexpected '{}'", t.text())),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn eval(node: &Node, shell: &Shell) -> Result<Number, String> {
+    match node {
+        Node::Num(n) => Ok(*n),
+        Node::Var(name) => {
+            let value = variable_value(name, shell);
+            if value.trim().is_empty() {
+                return Ok(Number::Int(0));
+            }
+            parse_number(&value).ok_or_else(|| format!("{name}: '{value}' is not a number"))
+        }
+        Node::Unary(op, operand) => {
+            let v = eval(operand, shell)?;
+            Ok(match (*op, v) {
+                ("-", Number::Int(n)) => Number::Int(n.wrapping_neg()),
+                ("-", Number::Float(x)) => Number::Float(-x),
+                ("+", v) => v,
+                ("!", v) => Number::Int(!v.is_true() as i64),
+                (_, v) => Number::Int(!v.int("~")?),
+            })
+        }
+        // 論理演算は短絡評価
+        Node::Binary("&&", lhs, rhs) => {
+            let v = eval(lhs, shell)?.is_true() && eval(rhs, shell)?.is_true();
+            Ok(Number::Int(v as i64))
+        }
+        Node::Binary("||", lhs, rhs) => {
+            let v = eval(lhs, shell)?.is_true() || eval(rhs, shell)?.is_true();
+            Ok(Number::Int(v as i64))
+        }
+        Node::Binary(op, lhs, rhs) => binary(op, eval(lhs, shell)?, eval(rhs, shell)?),
+        Node::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|a| eval(a, shell))
+                .collect::<Result<Vec<_>, _>>()?;
+            call(name, &args)
+        }
+    }
+}
+
+fn binary(op: &str, a: Number, b: Number) -> Result<Number, String> {
+    use Number::{Float, Int};
+    if matches!(op, "/" | "%") && b.as_f64() == 0.0 {
+        return Err("division by zero".to_string());
+    }
+    let cmp = |r: bool| Ok(Int(r as i64));
+    match (op, a, b) {
+        ("==", ..) => cmp(a.as_f64() == b.as_f64()),
+        ("!=", ..) => cmp(a.as_f64() != b.as_f64()),
+        ("<", ..) => cmp(a.as_f64() < b.as_f64()),
+        ("<=", ..) => cmp(a.as_f64() <= b.as_f64()),
+        (">", ..) => cmp(a.as_f64() > b.as_f64()),
+        (">=", ..) => cmp(a.as_f64() >= b.as_f64()),
+        ("&" | "|" | "^" | "<<" | ">>", ..) => {
+            let (x, y) = (a.int(op)?, b.int(op)?);
+            Ok(Int(match op {
+                "&" => x & y,
+                "|" => x | y,
+                "^" => x ^ y,
+                "<<" => x.wrapping_shl(y as u32),
+                _ => x.wrapping_shr(y as u32),
+            }))
+        }
+        ("**", Int(x), Int(y)) if y >= 0 => Ok(Int(x.wrapping_pow(y as u32))),
+        ("**", ..) => Ok(Float(a.as_f64().powf(b.as_f64()))),
+        (_, Int(x), Int(y)) => Ok(Int(match op {
+            "+" => x.wrapping_add(y),
+            "-" => x.wrapping_sub(y),
+            "*" => x.wrapping_mul(y),
+            "/" => x.wrapping_div(y),
+            _ => x.wrapping_rem(y),
+        })),
+        _ => {
+            let (x, y) = (a.as_f64(), b.as_f64());
+            Ok(Float(match op {
+                "+" => x + y,
+                "-" => x - y,
+                "*" => x * y,
+                "/" => x / y,
+                _ => x % y,
+            }))
+        }
+    }
+}
+
+fn call(name: &str, args: &[Number]) -> Result<Number, String> {
+    use Number::{Float, Int};
+    let one = || match args {
+        [a] => Ok(*a),
+        _ => Err(format!("{name}() takes 1 argument")),
+    };
+    // 小数を丸めて、収まるなら整数に戻す
+    let to_int = |x: f64| {
+        if x.is_finite() && x.abs() < i64::MAX as f64 {
+            Int(x as i64)
+        } else {
+            Float(x)
+        }
+    };
+    match name {
+        "abs" => Ok(match one()? {
+            Int(n) => Int(n.wrapping_abs()),
+            Float(x) => Float(x.abs()),
+        }),
+        "floor" => Ok(to_int(one()?.as_f64().floor())),
+        "ceil" => Ok(to_int(one()?.as_f64().ceil())),
+        "round" => Ok(to_int(one()?.as_f64().round())),
+        "sqrt" => Ok(Float(one()?.as_f64().sqrt())),
+        "min" | "max" => {
+            let mut best = *args
+                .first()
+                .ok_or_else(|| format!("{name}() needs at least 1 argument"))?;
+            for &v in &args[1..] {
+                let better = if name == "min" {
+                    v.as_f64() < best.as_f64()
+                } else {
+                    v.as_f64() > best.as_f64()
+                };
+                if better {
+                    best = v;
+                }
+            }
+            Ok(best)
+        }
+        _ => Err(format!("unknown function '{name}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(expr: &str) -> Result<String, String> {
+        let mut shell = Shell::new(false);
+        shell.variables.set("arith_x", "7".to_string());
+        shell.variables.set("arith_word", "abc".to_string());
+        eval_arith(expr, &shell).map(|n| n.to_string())
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(calc("1 + 2 * 3").unwrap(), "7");
+        assert_eq!(calc("(1 + 2) * 3").unwrap(), "9");
+        assert_eq!(calc("10 - 4 - 3").unwrap(), "3");
+        assert_eq!(calc("2 ** 3 ** 2").unwrap(), "512");
+        assert_eq!(calc("-2 + 5").unwrap(), "3");
+        assert_eq!(calc("1 < 2 && 3 > 4 || !0").unwrap(), "1");
+        assert_eq!(calc("6 & 3 | 8 ^ 1").unwrap(), "11");
+        assert_eq!(calc("1 << 4 >> 2").unwrap(), "4");
+    }
+
+    #[test]
+    fn integers_stay_integers_until_a_float_appears() {
+        assert_eq!(calc("7 / 2").unwrap(), "3");
+        assert_eq!(calc("7 % 3").unwrap(), "1");
+        assert_eq!(calc("7.0 / 2").unwrap(), "3.5");
+        assert_eq!(calc("0x1f + 1").unwrap(), "32");
+        assert_eq!(calc("1e3").unwrap(), "1000");
+        assert_eq!(calc("2 ** -1").unwrap(), "0.5");
+        assert_eq!(calc("").unwrap(), "0");
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        assert_eq!(calc("arith_x * 2").unwrap(), "14");
+        assert_eq!(calc("$arith_x + 1").unwrap(), "8");
+        assert_eq!(calc("arith_unset + 1").unwrap(), "1");
+        assert_eq!(calc("max(1, arith_x, 3)").unwrap(), "7");
+        assert_eq!(calc("min(2.5, 3)").unwrap(), "2.5");
+        assert_eq!(
+            calc("abs(-4) + floor(2.7) + ceil(0.2) + round(1.5)").unwrap(),
+            "9"
+        );
+        assert_eq!(calc("sqrt(16)").unwrap(), "4");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(calc("1 / 0").unwrap_err(), "division by zero");
+        assert_eq!(calc("1 % 0").unwrap_err(), "division by zero");
+        assert_eq!(calc("1.5 & 1").unwrap_err(), "'&' needs integer operands");
+        assert_eq!(calc("(1 + 2").unwrap_err(), "missing ')'");
+        assert_eq!(calc("1 2").unwrap_err(), "unexpected '2'");
+        assert_eq!(calc("1 @ 2").unwrap_err(), "unexpected character '@'");
+        assert_eq!(calc("nope(1)").unwrap_err(), "unknown function 'nope'");
+        assert_eq!(
+            calc("arith_word + 1").unwrap_err(),
+            "arith_word: 'abc' is not a number"
+        );
+    }
+}
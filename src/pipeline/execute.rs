@@ -2,24 +2,31 @@
 // 依存: std + libc（nix等は未使用）
 
 use std::{
-    fs::{self, File, OpenOptions},
-    io::{self, Write},
-    os::unix::process::CommandExt,
-    path::Path,
-    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{Command, Stdio},
 };
 
 use crate::{
     error::{Error, Result},
-    pipeline::parse::{CommandExpr, Expr, Redirection},
-    shell::{Shell, builtins::find},
+    pipeline::{
+        expand_brace::expand_braces,
+        expand_word::{expand_word, expand_word_glob, expand_word_joined},
+        parse::{CommandExpr, Expr, Group, PipeTo, RedirectTarget, Redirection, Segment, WordNode},
+    },
+    shell::{
+        Shell,
+        builtins::{Builtin, Streams, find},
+        exe_list::ExeList,
+        jobs::{self, Job},
+        variables::Scope,
+    },
 };
 
-/// 外部コマンドへ渡すための Stdio を継承FDから作る（dup 不要: inheritでOK）
-fn stdio_inherit() -> Stdio {
-    Stdio::inherit()
-}
-
 /// リダイレクト用ファイルオープン
 fn open_redirect_file(path: &str, append: bool) -> io::Result<File> {
     if append {
@@ -33,176 +40,755 @@ fn open_redirect_file(path: &str, append: bool) -> io::Result<File> {
     }
 }
 
-// --- 終了コード正規化 & wait_all -------------------------------------------
+/// 内容を書き込んで先頭に巻き戻した無名ファイル（ヒアドキュメント用）
+fn anonymous_file(content: &[u8]) -> io::Result<File> {
+    let fd = unsafe { libc::memfd_create(c"my_shell".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut f = unsafe { File::from_raw_fd(fd) };
+    f.write_all(content)?;
+    f.rewind()?;
+    Ok(f)
+}
 
-fn exit_code(status: ExitStatus) -> i32 {
-    use std::os::unix::process::ExitStatusExt;
-    if let Some(c) = status.code() {
-        c
-    } else if let Some(sig) = status.signal() {
-        128 + sig
-    } else {
-        1
+/// リダイレクト先の fd に割り当てるもの
+enum RedirectSource {
+    File(File), // 開いたファイル（fd は 10 以上へ退避済み）
+    Fd(i32),    // n>&m の m
+    Close,      // n>&-
+}
+
+impl RedirectSource {
+    /// dup2 の複製元。None は close
+    fn raw_fd(&self) -> Option<i32> {
+        match self {
+            RedirectSource::File(f) => Some(f.as_raw_fd()),
+            RedirectSource::Fd(fd) => Some(*fd),
+            RedirectSource::Close => None,
+        }
     }
 }
 
-fn wait_all(children: &mut Vec<Child>) -> Result<Vec<i32>> {
-    children
-        .drain(..)
-        .map(|mut c| Ok(exit_code(c.wait()?)))
-        .collect()
+/// 開いたファイルを 10 以上の fd へ移す（`3> file` などの対象 fd と衝突させない）
+fn move_high(f: File) -> io::Result<File> {
+    let fd = unsafe { libc::fcntl(f.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// リダイレクトを記述順に解決する（ファイルはここで開く）
+fn open_redirects(
+    redirects: &[Redirection],
+    shell: &mut Shell,
+) -> Result<Vec<(i32, RedirectSource)>> {
+    let mut opened = Vec::new();
+    for r in redirects {
+        let file = match &r.target {
+            RedirectTarget::File { path, append } => {
+                let p = expand_word_joined(path, shell)?;
+                open_redirect_file(&p, *append).map_err(|e| named_error(&p, e))?
+            }
+            RedirectTarget::Input(path) => {
+                let p = expand_word_joined(path, shell)?;
+                File::open(&p).map_err(|e| named_error(&p, e))?
+            }
+            RedirectTarget::HereDoc(body) => {
+                anonymous_file(expand_word_joined(body, shell)?.as_bytes())?
+            }
+            RedirectTarget::HereString(word) => {
+                anonymous_file((expand_word_joined(word, shell)? + "\n").as_bytes())?
+            }
+            RedirectTarget::Dup(src) => {
+                opened.push((r.fd, RedirectSource::Fd(*src)));
+                continue;
+            }
+            RedirectTarget::Close => {
+                opened.push((r.fd, RedirectSource::Close));
+                continue;
+            }
+        };
+        opened.push((r.fd, RedirectSource::File(move_high(file)?)));
+    }
+    Ok(opened)
+}
+
+fn named_error(path: &str, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path, e))
+}
+
+/// 自プロセスの fd を一時的に差し替える（ビルトイン実行用）。drop で元に戻す。
+struct FdGuard {
+    fd: i32,
+    saved: i32, // 元々開いていなければ -1
+}
+
+impl FdGuard {
+    /// target が None なら fd を閉じる
+    fn new(fd: i32, target: Option<i32>) -> io::Result<Self> {
+        io::stdout().flush()?;
+        let saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+        let ret = match target {
+            Some(target) if target == fd => 0,
+            Some(target) => unsafe { libc::dup2(target, fd) },
+            None => unsafe { libc::close(fd) },
+        };
+        if ret < 0 && target.is_some() {
+            let err = io::Error::last_os_error();
+            if saved >= 0 {
+                unsafe { libc::close(saved) };
+            }
+            return Err(err);
+        }
+        Ok(Self { fd, saved })
+    }
+}
+
+impl Drop for FdGuard {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        unsafe {
+            if self.saved >= 0 {
+                libc::dup2(self.saved, self.fd);
+                libc::close(self.saved);
+            } else {
+                libc::close(self.fd);
+            }
+        }
+    }
+}
+
+/// 差し替えた順と逆順に元へ戻す
+#[derive(Default)]
+struct FdGuards(Vec<FdGuard>);
+
+impl FdGuards {
+    fn push(&mut self, fd: i32, target: Option<i32>) -> io::Result<()> {
+        self.0.push(FdGuard::new(fd, target)?);
+        Ok(())
+    }
+}
+
+impl Drop for FdGuards {
+    fn drop(&mut self) {
+        while self.0.pop().is_some() {}
+    }
 }
 
 // --- エントリ ---------------------------------------------------------------
 
 pub fn execute(expr: &Expr, shell: &mut Shell) -> Result<i32> {
+    // exit の後は続くコマンドやスクリプトの残りを実行しない
+    if let Some(code) = shell.exit_requested {
+        return Ok(code);
+    }
     match expr {
-        Expr::And(lhs, rhs) => {
-            if execute(lhs, shell)? == 0 {
-                execute(rhs, shell)
-            } else {
-                Ok(1)
+        Expr::And(lhs, rhs) => match execute(lhs, shell)? {
+            0 => execute(rhs, shell),
+            code => Ok(code),
+        },
+        Expr::Or(lhs, rhs) => match execute(lhs, shell)? {
+            0 => Ok(0),
+            _ => execute(rhs, shell),
+        },
+        Expr::Pipe(commands) => execute_pipeline(commands, shell),
+        Expr::Seq(list) => {
+            let mut code = 0;
+            for expr in list {
+                code = execute(expr, shell)?;
             }
+            Ok(code)
         }
-        Expr::Or(lhs, rhs) => {
-            if execute(lhs, shell)? != 0 {
-                execute(rhs, shell)
-            } else {
-                Ok(1)
+        Expr::Background(expr, text) => execute_background(expr, text, shell),
+        Expr::Not(expr) => {
+            let code = if execute(expr, shell)? == 0 { 1 } else { 0 };
+            shell.status = code;
+            Ok(code)
+        }
+    }
+}
+
+/// 展開済みの argv を 1 つのコマンドとして実行する（env などのビルトインから使う）
+pub fn execute_argv(argv: &[String], shell: &mut Shell) -> Result<i32> {
+    let word = |s: &String| WordNode {
+        segments: vec![Segment::SingleQuoted(s.clone())],
+    };
+    let cmd = CommandExpr {
+        assignments: Vec::new(),
+        cmd_name: word(&argv[0]),
+        args: argv[1..].iter().map(word).collect(),
+        pipe: PipeTo::None,
+        redirects: Vec::new(),
+        group: None,
+    };
+    execute(&Expr::Pipe(vec![cmd]), shell)
+}
+
+/// Expr を fork した子シェルで実行する。子は setup の後に実行して終了し、親の後処理へは戻らない
+fn fork_shell(
+    expr: &Expr,
+    shell: &mut Shell,
+    setup: impl FnOnce() -> io::Result<()>,
+) -> io::Result<i32> {
+    fork_with(shell, setup, |shell| execute(expr, shell).unwrap_or(1))
+}
+
+/// fork した子で setup の後に body を実行し、その終了コードで終了する
+fn fork_with(
+    shell: &mut Shell,
+    setup: impl FnOnce() -> io::Result<()>,
+    body: impl FnOnce(&mut Shell) -> i32,
+) -> io::Result<i32> {
+    io::stdout().flush()?;
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            if let Err(e) = setup() {
+                eprintln!("{}", e);
+                unsafe { libc::_exit(1) }
             }
+            // 子のジョブは子が管理する。端末の受け渡しは親だけが行う
+            shell.job_control = false;
+            shell.jobs.clear();
+            shell.proc_substs.clear(); // 親のプロセス置換の fd は持たない
+            let code = body(shell);
+            let _ = io::stdout().flush();
+            unsafe { libc::_exit(code) }
         }
-        Expr::Pipe(commands) => execute_pipeline(commands, shell),
+        pid => Ok(pid),
     }
 }
 
-enum PipedOut {
-    External(ChildStdout),
-    Builtin(String),
-    None,
+/// `cmd &`: サブシェルを自分のプロセスグループで走らせ、ジョブとして登録する
+fn execute_background(expr: &Expr, text: &str, shell: &mut Shell) -> Result<i32> {
+    let job_control = shell.job_control;
+    let pid = fork_shell(expr, shell, || unsafe {
+        if job_control {
+            libc::setpgid(0, 0);
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+        }
+        // fg で前面に戻した後に ^Z で止まれるように
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+        libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+        libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+        Ok(())
+    })?;
+    let pgid = if job_control {
+        unsafe { libc::setpgid(pid, pid) };
+        pid
+    } else {
+        0
+    };
+    let id = shell.jobs.add(Job::new(pgid, vec![pid], text.to_string()));
+    eprintln!("[{}] {}", id, pid);
+    shell.status = 0;
+    Ok(0)
 }
 
-impl PipedOut {
-    #[inline]
-    fn take(&mut self) -> Self {
-        std::mem::replace(self, PipedOut::None)
+/// フォアグラウンドのジョブを待ち、各プロセスの終了コードを対応する段へ書き込む。
+/// ^Z で止まったら 128+SIGTSTP
+fn wait_stages(
+    job: Job,
+    stages: &[usize],
+    codes: &mut [i32],
+    shell: &mut Shell,
+) -> std::result::Result<(), i32> {
+    let statuses = shell
+        .jobs
+        .foreground(job, shell.job_control)
+        .ok_or(128 + libc::SIGTSTP)?;
+    for (&idx, code) in stages.iter().zip(statuses) {
+        codes[idx] = code;
     }
+    Ok(())
+}
+
+/// 各段の終了コードからパイプライン全体の終了コードを決め、$status / $pipestatus に残す
+fn finish_pipeline(codes: Vec<i32>, shell: &mut Shell) -> i32 {
+    let status = if shell.options.pipefail {
+        codes.iter().rev().find(|&&c| c != 0).copied().unwrap_or(0)
+    } else {
+        codes.last().copied().unwrap_or(0)
+    };
+    shell.pipestatus = codes;
+    shell.status = status;
+    status
+}
+
+/// パイプで次の段へ渡す fd を選ぶ
+fn pipe_fds(pipe: PipeTo) -> &'static [i32] {
+    match pipe {
+        PipeTo::None => &[],
+        PipeTo::Stdout => &[libc::STDOUT_FILENO],
+        PipeTo::Stderr => &[libc::STDERR_FILENO],
+        PipeTo::Both => &[libc::STDOUT_FILENO, libc::STDERR_FILENO],
+    }
+}
+
+fn pipe_str(pipe: PipeTo) -> &'static str {
+    match pipe {
+        PipeTo::None => "",
+        PipeTo::Stdout => " | ",
+        PipeTo::Stderr => " 2| ",
+        PipeTo::Both => " &| ",
+    }
+}
+
+/// パイプラインの子プロセス（exec 前 / サブシェル）の初期化。
+/// プロセスグループへ入り、シェルが無視しているシグナルを既定に戻す
+unsafe fn setup_child(job_control: bool, pgid: i32) {
+    unsafe {
+        if job_control {
+            // 親側でも setpgid するが、exec 前に端末を渡しておかないと SIGTTIN で止まる
+            libc::setpgid(0, pgid);
+            libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+        }
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+        libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+        libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+    }
+}
+
+/// (fd, 複製元) を記述順に dup2 する。複製元 None は close（子プロセスで呼ぶ）
+unsafe fn apply_fd_ops(ops: &[(i32, Option<i32>)]) -> io::Result<()> {
+    for &(fd, src) in ops {
+        unsafe {
+            match src {
+                // 同じ fd への複製は close-on-exec を外すだけ
+                Some(src) if src == fd => {
+                    let flags = libc::fcntl(fd, libc::F_GETFD);
+                    if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Some(src) => {
+                    if libc::dup2(src, fd) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                None => {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 子プロセスをパイプラインのプロセスグループへ入れる（最初の 1 つがグループ ID になる）
+fn join_group(pid: i32, pgid: &mut i32, job_control: bool) {
+    if job_control {
+        if *pgid == 0 {
+            *pgid = pid;
+        }
+        unsafe { libc::setpgid(pid, *pgid) };
+    }
+}
+
+/// `{ ...; }` を今のシェルで実行する。標準入力とリダイレクトは実行中だけ差し替える
+fn run_group(
+    expr: &Expr,
+    piped_in: Option<OwnedFd>,
+    redirects: &[(i32, RedirectSource)],
+    shell: &mut Shell,
+) -> Result<i32> {
+    let mut guards = FdGuards::default();
+    if let Some(fd) = &piped_in {
+        guards.push(libc::STDIN_FILENO, Some(fd.as_raw_fd()))?;
+    }
+    for (fd, src) in redirects {
+        guards.push(*fd, src.raw_fd())?;
+    }
+    execute(expr, shell)
+}
+
+/// ビルトインを今のシェルで実行する。標準入力とリダイレクトは実行中だけ差し替える
+fn run_builtin(
+    builtin: &dyn Builtin,
+    args: &[String],
+    piped_in: Option<OwnedFd>,
+    redirects: &[(i32, RedirectSource)],
+    shell: &mut Shell,
+) -> i32 {
+    let piped = piped_in.is_some();
+    let run = |shell: &mut Shell| -> io::Result<i32> {
+        let mut guards = FdGuards::default();
+        if let Some(fd) = &piped_in {
+            guards.push(libc::STDIN_FILENO, Some(fd.as_raw_fd()))?;
+        }
+        for (fd, src) in redirects {
+            guards.push(*fd, src.raw_fd())?;
+        }
+        Ok(builtin.run(shell, args, &mut Streams::std_piped(piped)))
+    };
+    match run(shell) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}: {}", builtin.name(), e);
+            1
+        }
+    }
+}
+
+/// 実行できなかった段の出力の代わり。次の段がシェルの stdin（端末）から読まないよう、空の入力を渡す
+fn empty_input(pipe: PipeTo) -> Option<OwnedFd> {
+    match pipe {
+        PipeTo::None => None,
+        _ => File::open("/dev/null").ok().map(OwnedFd::from),
+    }
+}
+
+/// `NAME=value builtin` の値をエクスポートした変数として 1 段内側のスコープに入れる。
+/// ビルトインの実行が終わったら pop_scope で戻す
+fn push_assignments(assigns: &[(String, String)], shell: &mut Shell) {
+    shell.variables.push_scope();
+    for (name, value) in assigns {
+        shell
+            .variables
+            .set_in(Scope::Local, name, value.clone(), Some(true));
+    }
+}
+
+/// パイプラインの途中の段（グループやビルトイン）を fork した子で body として実行する。
+/// パイプでつなぐなら読み出し側を返す
+fn spawn_stage(
+    pipe: PipeTo,
+    piped_in: Option<OwnedFd>,
+    redirects: &[(i32, RedirectSource)],
+    pgid: i32,
+    shell: &mut Shell,
+    body: impl FnOnce(&mut Shell) -> i32,
+) -> Result<(i32, Option<OwnedFd>)> {
+    let (read_end, write_end) = match pipe {
+        PipeTo::None => (None, None),
+        _ => {
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let [r, w] = fds.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+            (Some(r), Some(w))
+        }
+    };
+
+    let mut ops: Vec<(i32, Option<i32>)> = Vec::new();
+    if let Some(fd) = &piped_in {
+        ops.push((libc::STDIN_FILENO, Some(fd.as_raw_fd())));
+    }
+    if let Some(w) = &write_end {
+        for &fd in pipe_fds(pipe) {
+            ops.push((fd, Some(w.as_raw_fd())));
+        }
+    }
+    ops.extend(redirects.iter().map(|(fd, src)| (*fd, src.raw_fd())));
+
+    let job_control = shell.job_control;
+    let read_fd = read_end.as_ref().map(|r| r.as_raw_fd());
+    let setup = || unsafe {
+        setup_child(job_control, pgid);
+        // 読み出し側を子が握ったままだと、次の段が終わっても書き込み側に SIGPIPE が届かない
+        if let Some(fd) = read_fd {
+            libc::close(fd);
+        }
+        apply_fd_ops(&ops)
+    };
+    let pid = fork_with(shell, setup, body)?;
+    Ok((pid, read_end))
 }
 
 // --- 中核: パイプライン実行 -------------------------------------------------
 
+/// 起動済みの段。待たずに戻る（途中の段の起動や展開に失敗した）ときは drop で止めて回収する
+struct Spawned {
+    pgid: i32,
+    pids: Vec<i32>,
+    job_control: bool,
+}
+
+impl Spawned {
+    /// 待つためにジョブへ渡す（以後 drop では何もしない）
+    fn into_job(mut self, text: String) -> Job {
+        Job::new(self.pgid, std::mem::take(&mut self.pids), text)
+    }
+}
+
+impl Drop for Spawned {
+    fn drop(&mut self) {
+        if self.pids.is_empty() {
+            return;
+        }
+        unsafe {
+            // サブシェルの段が起動した孫もまとめて止める
+            if self.job_control && self.pgid > 0 {
+                libc::kill(-self.pgid, libc::SIGKILL);
+            }
+            for &pid in &self.pids {
+                libc::kill(pid, libc::SIGKILL);
+            }
+            for &pid in &self.pids {
+                let mut status = 0;
+                libc::waitpid(pid, &mut status, 0);
+            }
+        }
+        if self.job_control {
+            jobs::take_terminal();
+        }
+    }
+}
+
 fn execute_pipeline(commands: &[CommandExpr], shell: &mut Shell) -> Result<i32> {
+    let base = shell.proc_substs.len();
+    let result = execute_stages(commands, shell);
+    reap_proc_substs(base, shell);
+    result
+}
+
+fn execute_stages(commands: &[CommandExpr], shell: &mut Shell) -> Result<i32> {
     if commands.is_empty() {
         return Ok(0);
     }
 
-    let mut children: Vec<Child> = Vec::new();
-    let mut piped_out = PipedOut::None;
-
-    for (i, cmd) in commands.iter().enumerate() {
-        let is_last = i == commands.len() - 1;
+    let job_control = shell.job_control;
+    // 最初に起動した段の pid をパイプライン全体のプロセスグループにする
+    let mut spawned = Spawned {
+        pgid: 0,
+        pids: Vec::new(),
+        job_control,
+    };
+    let mut stages: Vec<usize> = Vec::new(); // pids に対応する段の番号
+    let mut text = String::new(); // ジョブ表示用
+    // 前段の出力（パイプの読み出し側）
+    let mut piped_out: Option<OwnedFd> = None;
+    // 各段の終了コード（子プロセスの分は待ち終えてから埋める）
+    let mut codes: Vec<i32> = Vec::new();
+
+    'stages: for cmd in commands {
+        // ===== グループ =====
+        if let Some(group) = &cmd.group {
+            let piped_in = piped_out.take();
+            let redirects = match open_redirects(&cmd.redirects, shell) {
+                Ok(r) => r,
+                Err(Error::Io(e)) => {
+                    eprintln!("{}", e);
+                    codes.push(1);
+                    piped_out = empty_input(cmd.pipe);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            match group {
+                // 最後の段なら今のシェルで実行する（cd などが残る）
+                Group::Current(expr, source) if cmd.pipe == PipeTo::None => {
+                    text += source;
+                    codes.push(run_group(expr, piped_in, &redirects, shell)?);
+                }
+                Group::Current(expr, source) | Group::Subshell(expr, source) => {
+                    text += source;
+                    text += pipe_str(cmd.pipe);
+                    let (pid, out) = spawn_stage(
+                        cmd.pipe,
+                        piped_in,
+                        &redirects,
+                        spawned.pgid,
+                        shell,
+                        |shell| execute(expr, shell).unwrap_or(1),
+                    )?;
+                    join_group(pid, &mut spawned.pgid, job_control);
+                    piped_out = out;
+                    stages.push(codes.len());
+                    codes.push(0);
+                    spawned.pids.push(pid);
+                }
+            }
+            continue;
+        }
 
-        // ▼ WordNode → String（ここで確定）
-        let cmd_name_str = &cmd.cmd_name.concat_text(&shell);
-        let mut args_str = vec![];
+        // ▼ WordNode → String（ここで確定）。ブレース展開 → 変数などの展開 → グロブの順
+        let subst_base = shell.proc_substs.len();
+        // NAME=value の値は分割もグロブもしない
+        let mut assigns = Vec::new();
+        for (name, value) in &cmd.assignments {
+            assigns.push((name.clone(), expand_word_joined(value, shell)?));
+        }
+        let mut args_str = Vec::new();
+        // 代入だけの行ではコマンド名が空
+        let words = if cmd.cmd_name.segments.is_empty() {
+            Vec::new()
+        } else {
+            expand_braces(&cmd.cmd_name)
+        };
+        for word in words {
+            args_str.append(&mut expand_word(&word, shell)?);
+        }
         for arg in cmd.args.iter() {
-            let w = arg.concat_text(shell);
-            args_str.append(&mut expand_glob(w));
+            for word in expand_braces(arg) {
+                match expand_word_glob(&word, shell) {
+                    Ok(mut fields) => args_str.append(&mut fields),
+                    // fish と同じく、このコマンドだけ 124 で失敗させて && / || や次のコマンドへ進む
+                    Err(e @ Error::NoMatch(_)) => {
+                        eprintln!("{}", e);
+                        codes.push(124);
+                        piped_out = empty_input(cmd.pipe);
+                        continue 'stages;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
         }
-        let mut pending_stdin_from_builtin: Option<String> = None;
+        if args_str.is_empty() {
+            // コマンドのない代入はシェル変数に入れる
+            if !assigns.is_empty() {
+                for (name, value) in assigns {
+                    shell.variables.set(&name, value);
+                }
+                codes.push(0);
+            }
+            piped_out = empty_input(cmd.pipe);
+            continue;
+        }
+        // この段の引数に現れたプロセス置換の fd（この段の子にだけ引き継ぐ）
+        let subst_fds: Vec<i32> = shell.proc_substs[subst_base..]
+            .iter()
+            .map(|(fd, _)| fd.as_raw_fd())
+            .collect();
+        let cmd_name_str = &args_str.remove(0);
+        // exit 以外のコマンドを挟んだら、ジョブが残っているときの exit はまた警告する
+        if cmd_name_str != "exit" {
+            shell.exit_warned = false;
+        }
+        let piped_in = piped_out.take();
+        text += &assigns
+            .iter()
+            .map(|(name, value)| format!("{name}={value} "))
+            .collect::<String>();
+        text += &[cmd_name_str.as_str()]
+            .into_iter()
+            .chain(args_str.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        text += pipe_str(cmd.pipe);
+
+        let redirects = match open_redirects(&cmd.redirects, shell) {
+            Ok(r) => r,
+            Err(Error::Io(e)) => {
+                eprintln!("{}", e);
+                codes.push(1);
+                piped_out = empty_input(cmd.pipe);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
 
         // ===== ビルトインか？ =====
         if let Some(bi) = find(cmd_name_str) {
-            wait_all(&mut children)?;
-            let ret = bi.run(shell, &args_str);
-            let mut piped = String::new();
-            match &cmd.stdout {
-                Redirection::File { path, append } => {
-                    let p = path.concat_text(&shell);
-                    let mut f = open_redirect_file(&p, *append)?;
-                    f.write_all(ret.stdout.as_bytes())?;
+            // 最後の段は今のシェルで実行する（cd や set が残る）。前の段とは並んで動き、パイプから読める
+            if cmd.pipe == PipeTo::None {
+                // 前の段が端末から読めるよう、実行中は端末を渡しておく
+                let give = job_control && spawned.pgid > 0;
+                if give {
+                    jobs::give_terminal(spawned.pgid);
                 }
-                Redirection::Inherit => {
-                    print!("{}", ret.stdout);
+                // 代入が無ければスコープは積まない（set -l は今のスコープに入る）
+                let temporary = !assigns.is_empty();
+                if temporary {
+                    push_assignments(&assigns, shell);
                 }
-                Redirection::Pipe => {
-                    piped += &ret.stdout;
+                codes.push(run_builtin(bi, &args_str, piped_in, &redirects, shell));
+                if temporary {
+                    shell.variables.pop_scope();
                 }
-            }
-            match &cmd.stderr {
-                Redirection::File { path, append } => {
-                    let p = path.concat_text(&shell);
-                    let mut f = open_redirect_file(&p, *append)?;
-                    f.write_all(ret.stderr.as_bytes())?;
-                }
-                Redirection::Inherit => {
-                    eprint!("{}", ret.stderr);
-                }
-                Redirection::Pipe => {
-                    piped += &ret.stderr;
+                if give {
+                    jobs::take_terminal();
                 }
+                continue;
             }
-            piped_out = PipedOut::Builtin(piped);
+            // 途中の段は fork した子で実行し、出力を次の段へ流す
+            let piped = piped_in.is_some();
+            let (pid, out) = spawn_stage(
+                cmd.pipe,
+                piped_in,
+                &redirects,
+                spawned.pgid,
+                shell,
+                |shell| {
+                    push_assignments(&assigns, shell);
+                    bi.run(shell, &args_str, &mut Streams::std_piped(piped))
+                },
+            )?;
+            join_group(pid, &mut spawned.pgid, job_control);
+            piped_out = out;
+            stages.push(codes.len());
+            codes.push(0);
+            spawned.pids.push(pid);
+            continue;
+        }
+
+        // ===== 見つからない・実行できない =====
+        // 報告（とハンドラ）は子で実行し、その段の終了コードを 127 / 126 にする。リダイレクトやパイプもそのまま効く
+        // `PATH=... cmd` なら、探すのも候補を出すのもその PATH
+        let path = match assigns.iter().rfind(|(name, _)| name == "PATH") {
+            Some((_, value)) => value.as_str(),
+            None => shell.variables.get("PATH").unwrap_or_default(),
+        };
+        if let Some((code, reason)) = ExeList::unrunnable(cmd_name_str, path) {
+            let (pid, out) = spawn_stage(
+                cmd.pipe,
+                piped_in,
+                &redirects,
+                spawned.pgid,
+                shell,
+                |shell| {
+                    push_assignments(&assigns, shell);
+                    shell.command_not_found(cmd_name_str, &args_str, code, reason)
+                },
+            )?;
+            join_group(pid, &mut spawned.pgid, job_control);
+            piped_out = out;
+            stages.push(codes.len());
+            codes.push(0);
+            spawned.pids.push(pid);
             continue;
         }
 
         // ===== 外部コマンド =====
         let mut c = Command::new(cmd_name_str);
         c.args(&args_str);
-        let pre_piped_out = piped_out.take();
-
-        // stdin 1
-        match pre_piped_out {
-            PipedOut::None => {
-                c.stdin(Stdio::inherit());
-            }
-            PipedOut::Builtin(s) => {
-                // ここでは pipe を開くだけ。実際の書き込みは spawn 後に行う
-                c.stdin(Stdio::piped());
-                pending_stdin_from_builtin = Some(s);
-            }
-            PipedOut::External(child_out) => {
-                // ここで所有権を消費してそのまま子プロセスの stdin につなぐ
-                c.stdin(Stdio::from(child_out));
-            }
-        };
-
-        // stdout
-        match &cmd.stdout {
-            Redirection::Pipe => {
+        // 環境変数はエクスポートした変数と、このコマンドだけの代入
+        c.env_clear();
+        c.envs(shell.variables.exported());
+        c.envs(assigns.iter().map(|(name, value)| (name, value)));
+        if let Some(fd) = piped_in {
+            c.stdin(Stdio::from(fd));
+        }
+        match cmd.pipe {
+            PipeTo::None => {}
+            PipeTo::Stdout | PipeTo::Both => {
                 c.stdout(Stdio::piped());
             }
-            Redirection::Inherit => {
-                c.stdout(stdio_inherit());
-            }
-            Redirection::File { path, append } => {
-                let p = path.concat_text(&shell);
-                let f = open_redirect_file(&p, *append)?;
-                c.stdout(Stdio::from(f));
+            PipeTo::Stderr => {
+                c.stderr(Stdio::piped());
             }
         }
 
-        // stderr
-        match &cmd.stderr {
-            Redirection::Pipe => {
-                c.stderr(Stdio::piped());
-            }
-            Redirection::Inherit => {
-                c.stderr(stdio_inherit());
-            }
-            Redirection::File { path, append } => {
-                let p = path.concat_text(&shell);
-                let f = open_redirect_file(&p, *append)?;
-                c.stderr(Stdio::from(f));
-            }
+        // 子プロセス側で記述順に dup2 する（&| は 2>&1 を先頭に置くのと同じ）
+        let mut ops: Vec<(i32, Option<i32>)> = Vec::new();
+        if cmd.pipe == PipeTo::Both {
+            ops.push((libc::STDERR_FILENO, Some(libc::STDOUT_FILENO)));
         }
+        ops.extend(redirects.iter().map(|(fd, src)| (*fd, src.raw_fd())));
+        let pgid = spawned.pgid;
         unsafe {
-            c.pre_exec(|| {
-                libc::signal(libc::SIGINT, libc::SIG_DFL);
-                libc::signal(libc::SIGQUIT, libc::SIG_DFL);
-                libc::signal(libc::SIGTSTP, libc::SIG_DFL);
-                libc::signal(libc::SIGTTIN, libc::SIG_DFL);
-                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
-                Ok(())
+            c.pre_exec(move || {
+                setup_child(job_control, pgid);
+                for &fd in &subst_fds {
+                    libc::fcntl(fd, libc::F_SETFD, 0);
+                }
+                apply_fd_ops(&ops)
             });
         }
 
@@ -210,83 +796,141 @@ fn execute_pipeline(commands: &[CommandExpr], shell: &mut Shell) -> Result<i32>
         let mut child = match c.spawn() {
             Ok(ch) => ch,
             Err(e) => {
+                // 調べた後に消えた、実行形式でない、など。式の残りは続ける
                 eprintln!("Failed to start '{}': {}", cmd_name_str, e);
-                return Err(Error::NoChild);
+                codes.push(if e.kind() == io::ErrorKind::NotFound {
+                    127
+                } else {
+                    126
+                });
+                piped_out = empty_input(cmd.pipe);
+                continue;
             }
         };
-
-        // stdin 2
-        if let Some(s) = pending_stdin_from_builtin
-            && let Some(mut stdin) = child.stdin.take()
-        {
-            use std::io::Write;
-            stdin.write_all(s.as_bytes())?;
-            drop(stdin);
-        }
-        if !is_last {
-            piped_out = PipedOut::External(child.stdout.take().unwrap());
-        }
-        children.push(child);
+        let pid = child.id() as i32;
+        join_group(pid, &mut spawned.pgid, job_control);
+        // 開いたファイルは子へ渡し終えたので閉じる
+        drop(redirects);
+
+        piped_out = match cmd.pipe {
+            PipeTo::None => None,
+            PipeTo::Stdout | PipeTo::Both => child.stdout.take().map(OwnedFd::from),
+            PipeTo::Stderr => child.stderr.take().map(OwnedFd::from),
+        };
+        stages.push(codes.len());
+        codes.push(0);
+        spawned.pids.push(pid);
     }
 
-    let codes = wait_all(&mut children)?;
-    Ok(*codes.last().unwrap_or(&0))
+    if !spawned.pids.is_empty()
+        && let Err(code) = wait_stages(spawned.into_job(text), &stages, &mut codes, shell)
+    {
+        shell.status = code;
+        return Ok(code);
+    }
+    Ok(finish_pipeline(codes, shell))
 }
 
-fn expand_glob(word: String) -> Vec<String> {
-    let path = Path::new(&word);
-    let (dir, file) = match (path.parent(), path.file_name()) {
-        (Some(d), Some(f)) => (d, f),
-        _ => return vec![word],
-    };
-    let file_str = file.to_string_lossy();
-    let dir = if dir == Path::new("") {
-        Path::new("./")
+/// `<(cmd)` / `>(cmd)`: cmd を子シェルで走らせてパイプでつなぎ、`/dev/fd/N` を返す。
+/// 親側の fd と pid は Shell::proc_substs に積み、パイプラインの終わりに閉じて回収する
+pub(super) fn spawn_proc_subst(expr: &Expr, input: bool, shell: &mut Shell) -> Result<String> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let [read_fd, write_fd] = fds;
+    // <(cmd) は cmd の stdout を、>(cmd) は cmd の stdin をパイプにする
+    let (ours, theirs, target) = if input {
+        (read_fd, write_fd, libc::STDOUT_FILENO)
     } else {
-        dir
+        (write_fd, read_fd, libc::STDIN_FILENO)
     };
+    let pid = fork_shell(expr, shell, || unsafe {
+        libc::dup2(theirs, target);
+        libc::close(ours);
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+        Ok(())
+    });
+    unsafe { libc::close(theirs) };
+    let ours = unsafe { File::from_raw_fd(ours) };
+    let pid = pid?;
+    // 低い番号のままだとリダイレクトの dup2 で上書きされうる
+    let ours = OwnedFd::from(move_high(ours)?);
+    let path = format!("/dev/fd/{}", ours.as_raw_fd());
+    shell.proc_substs.push((ours, pid));
+    Ok(path)
+}
 
-    if !file.to_string_lossy().to_string().contains("*") {
-        return vec![word];
+/// base 以降に積まれたプロセス置換の fd をすべて閉じてから、子を回収する
+fn reap_proc_substs(base: usize, shell: &mut Shell) {
+    if shell.proc_substs.len() <= base {
+        return;
+    }
+    let pids: Vec<i32> = shell
+        .proc_substs
+        .split_off(base)
+        .into_iter()
+        .map(|(_, pid)| pid)
+        .collect();
+    for pid in pids {
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
     }
+}
 
-    let Ok(entries) = fs::read_dir(dir) else {
-        return vec![word];
+/// Expr をサブシェル（fork した子）で実行し、標準出力を文字列として回収する
+pub(super) fn capture_output(expr: &Expr, shell: &mut Shell) -> Result<String> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let [read_fd, write_fd] = fds;
+    let pid = match fork_shell(expr, shell, || unsafe {
+        // 子: stdout をパイプへ差し替える
+        libc::dup2(write_fd, libc::STDOUT_FILENO);
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+        Ok(())
+    }) {
+        Ok(pid) => pid,
+        Err(e) => {
+            unsafe {
+                libc::close(read_fd);
+                libc::close(write_fd);
+            }
+            return Err(e.into());
+        }
     };
+    unsafe { libc::close(write_fd) };
+    let mut out = Vec::new();
+    let mut reader = unsafe { File::from_raw_fd(read_fd) };
+    let read_result = reader.read_to_end(&mut out);
+    let mut status = 0;
+    unsafe { libc::waitpid(pid, &mut status, 0) };
+    read_result?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
 
-    // グロブパターンを簡易的に正規表現に変換
-    let pattern = "^".to_string() + &regex_escape(&file_str).replace("*", ".*") + "$";
+#[cfg(test)]
+mod tests {
+    use std::env;
 
-    let re = match regex::Regex::new(&pattern) {
-        Ok(r) => r,
-        Err(_) => return vec![word],
-    };
+    use super::*;
+    use crate::pipeline::{parse::parse, tokenize::tokenize};
 
-    // マッチするファイル名を収集
-    let mut matches = Vec::new();
-    for entry in entries.flatten() {
-        if let Some(name) = entry.file_name().to_str() {
-            if re.is_match(name) && !name.starts_with('.') {
-                matches.push(entry.path().display().to_string());
-            }
-        }
+    /// src をサブシェルで実行した標準出力
+    fn output(src: &str) -> String {
+        let expr = parse(&tokenize(src)).unwrap();
+        capture_output(&expr, &mut Shell::new(false)).unwrap()
     }
 
-    // マッチしたものをスペースで結合して返す
-    if matches.is_empty() {
-        vec![word]
-    } else {
-        matches
+    #[test]
+    fn children_get_pwd_and_oldpwd_after_cd() {
+        let prev = format!("OLDPWD={}", env::current_dir().unwrap().display());
+        let out = output("cd /; env");
+        let vars: Vec<&str> = out.lines().collect();
+        assert!(vars.contains(&"PWD=/"), "{out}");
+        assert!(vars.contains(&prev.as_str()), "{out}");
     }
 }
-
-fn regex_escape(s: &str) -> String {
-    s.chars()
-        .flat_map(|c| match c {
-            '.' | '+' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' | '\\' | '?' => {
-                vec!['\\', c]
-            }
-            _ => vec![c],
-        })
-        .collect()
-}
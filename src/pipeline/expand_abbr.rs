@@ -1,8 +1,8 @@
-use super::tokenize::{QuoteKind, Token, tokenize};
+use super::tokenize::{QuoteKind, Token, Tokens, tokenize};
 use crate::shell::Shell;
 
 /// abbr 展開（最後が**Unquoted** な Word の時のみ）
-pub fn expand_abbr(mut tokens: Vec<Token>, shell: &Shell) -> Option<Vec<Token>> {
+pub fn expand_abbr(mut tokens: Tokens, shell: &Shell) -> Option<Tokens> {
     let Some(last_idx) = tokens
         .iter()
         .rposition(|t| matches!(t, Token::Word(_, QuoteKind::None)))
@@ -18,7 +18,7 @@ pub fn expand_abbr(mut tokens: Vec<Token>, shell: &Shell) -> Option<Vec<Token>>
         && let Some(expansion) = { shell.abbrs.get(word).cloned() }
     {
         let repl = tokenize(&expansion);
-        tokens.splice(last_idx..=last_idx, repl);
+        tokens.splice(last_idx, repl);
     }
 
     Some(tokens)
@@ -34,8 +34,13 @@ pub(super) fn is_command_position(tokens: &[Token], idx_of_current_word: usize)
         | Token::PipeBoth   // &|
         | Token::And        // &&
         | Token::Or         // ||
+        | Token::Semicolon  // ;
+        | Token::Background // &
+        | Token::Newline    // 改行
         => true,
         Token::Delimiter => is_command_position(tokens, idx_of_current_word-1),
+        // `{ cmd` のグループ先頭、`! cmd` / `not cmd` の否定
+        Token::Word(ref w, QuoteKind::None) if matches!(w.as_str(), "{" | "!" | "not") => is_command_position(tokens, idx_of_current_word-1),
         _ => false,
     }
 }
@@ -1,9 +1,9 @@
-use super::tokenize::{QuoteKind, Token, tokenize};
+use super::tokenize::{QuoteKind, Token, Tokens, tokenize};
 use crate::shell::Shell;
 
 /// alias 展開（コマンド先頭のみ / QuoteKind::None のみ）
-/// コマンド先頭は文頭または `|`, `2|`, `&|`, `&&`, `||` の直後。
-pub fn expand_aliases(mut tokens: Vec<Token>, shell: &Shell) -> Vec<Token> {
+/// コマンド先頭は文頭または `|`, `2|`, `&|`, `&&`, `||`, `;`, `&`, 改行, `{`, `!`, `not` の直後。
+pub fn expand_aliases(mut tokens: Tokens, shell: &Shell) -> Tokens {
     let mut at_cmd_head = true;
     let mut i = 0;
 
@@ -15,7 +15,7 @@ pub fn expand_aliases(mut tokens: Vec<Token>, shell: &Shell) -> Vec<Token> {
 
                 if let Some(expansion) = expansion_opt {
                     let expanded = tokenize(&expansion);
-                    tokens.splice(i..i + 1, expanded);
+                    tokens.splice(i, expanded);
                 }
             }
         }
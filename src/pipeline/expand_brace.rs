@@ -0,0 +1,281 @@
+use super::parse::{Segment, WordNode};
+
+/// ブレース展開の対象となる最小単位。クォートや変数などは丸ごと 1 つの Atom として扱い、
+/// 中の `{` `,` `}` には反応しない
+#[derive(Clone)]
+enum Piece {
+    Char(char),    // クォートされていない 1 文字
+    Atom(Segment), // それ以外のセグメント
+}
+
+/// `{a,b}` `{1..10}` `{a..e..2}` などを展開する（入れ子可）。展開するものがなければそのまま 1 つ返す
+pub fn expand_braces(word: &WordNode) -> Vec<WordNode> {
+    let mut pieces = Vec::new();
+    for seg in &word.segments {
+        match seg {
+            Segment::Unquoted(t) => pieces.extend(t.chars().map(Piece::Char)),
+            seg => pieces.push(Piece::Atom(seg.clone())),
+        }
+    }
+    expand(&pieces).into_iter().map(to_word).collect()
+}
+
+fn expand(pieces: &[Piece]) -> Vec<Vec<Piece>> {
+    for open in 0..pieces.len() {
+        if !is_char(&pieces[open], '{') {
+            continue;
+        }
+        let Some((close, commas)) = find_close(pieces, open) else {
+            continue;
+        };
+        let body = &pieces[open + 1..close];
+        let alternatives: Vec<Vec<Piece>> = if !commas.is_empty() {
+            let mut alts = Vec::new();
+            let mut start = open + 1;
+            for &comma in commas.iter().chain([&close]) {
+                alts.push(pieces[start..comma].to_vec());
+                start = comma + 1;
+            }
+            alts
+        } else if let Some(items) = plain_text(body).and_then(|s| expand_range(&s)) {
+            items
+                .into_iter()
+                .map(|s| s.chars().map(Piece::Char).collect())
+                .collect()
+        } else {
+            // `{}` や `{a}` はそのまま
+            continue;
+        };
+
+        let prefix = &pieces[..open];
+        let suffix = &pieces[close + 1..];
+        let mut result = Vec::new();
+        for alt in alternatives {
+            let mut word = prefix.to_vec();
+            word.extend(alt);
+            word.extend_from_slice(suffix);
+            result.extend(expand(&word));
+        }
+        return result;
+    }
+    vec![pieces.to_vec()]
+}
+
+fn is_char(piece: &Piece, c: char) -> bool {
+    matches!(piece, Piece::Char(x) if *x == c)
+}
+
+/// open の `{` に対応する `}` の位置と、その間の（入れ子でない）`,` の位置
+fn find_close(pieces: &[Piece], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, piece) in pieces.iter().enumerate().skip(open) {
+        match piece {
+            Piece::Char('{') => depth += 1,
+            Piece::Char('}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((i, commas));
+                }
+            }
+            Piece::Char(',') if depth == 1 => commas.push(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// すべてクォートされていない文字なら文字列にする
+fn plain_text(pieces: &[Piece]) -> Option<String> {
+    pieces
+        .iter()
+        .map(|p| match p {
+            Piece::Char(c) => Some(*c),
+            Piece::Atom(_) => None,
+        })
+        .collect()
+}
+
+/// `x..y` / `x..y..step`（数値か英字 1 文字）
+fn expand_range(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (
+            *start,
+            *end,
+            step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        ),
+        _ => return None,
+    };
+
+    if let (Ok(a), Ok(b)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // どちらかが 0 始まりなら長い方の桁数にそろえる
+        let padded = |s: &str| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            range(a, b, step)?
+                .map(|n| {
+                    if n < 0 {
+                        format!("-{:0w$}", n.unsigned_abs(), w = width.saturating_sub(1))
+                    } else {
+                        format!("{:0w$}", n, w = width)
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let (a, b) = (single_letter(start)?, single_letter(end)?);
+    Some(
+        range(a as i64, b as i64, step)?
+            .map(|n| (n as u8 as char).to_string())
+            .collect(),
+    )
+}
+
+fn single_letter(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// 範囲展開で作る単語の上限。超える範囲は展開せずそのまま残す
+const MAX_RANGE_LEN: u64 = 100_000;
+
+/// a から b まで（逆順も可）step ごと。幅があふれるか多すぎるなら None
+fn range(a: i64, b: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = a.checked_sub(b)?.checked_abs()? as u64 / step + 1;
+    if count > MAX_RANGE_LEN {
+        return None;
+    }
+    let step = i64::try_from(step).ok()?;
+    let dir = if a <= b { step } else { -step };
+    Some((0..count as i64).map(move |i| a + i * dir))
+}
+
+fn to_word(pieces: Vec<Piece>) -> WordNode {
+    let mut word = WordNode::new();
+    let mut text = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Char(c) => text.push(c),
+            Piece::Atom(seg) => {
+                if !text.is_empty() {
+                    word.segments
+                        .push(Segment::Unquoted(std::mem::take(&mut text)));
+                }
+                word.segments.push(seg);
+            }
+        }
+    }
+    if !text.is_empty() {
+        word.segments.push(Segment::Unquoted(text));
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{
+        parse::{Expr, parse},
+        tokenize::tokenize,
+    };
+
+    /// クォートされていない text を展開した各単語
+    fn braces(text: &str) -> Vec<String> {
+        words(&WordNode {
+            segments: vec![Segment::Unquoted(text.to_string())],
+        })
+    }
+
+    /// src を 1 語のコマンドとして構文解析してから展開する（`\` のエスケープを含められる）
+    fn parsed(src: &str) -> Vec<String> {
+        let Expr::Pipe(cmds) = parse(&tokenize(src)).unwrap() else {
+            unreachable!()
+        };
+        words(&cmds[0].cmd_name)
+    }
+
+    fn words(word: &WordNode) -> Vec<String> {
+        expand_braces(word)
+            .iter()
+            .map(|w| {
+                w.segments
+                    .iter()
+                    .map(|seg| match seg {
+                        Segment::Unquoted(t) | Segment::SingleQuoted(t) => t.clone(),
+                        _ => unreachable!(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(braces("{a,b{1,2}}x"), ["ax", "b1x", "b2x"]);
+        assert_eq!(braces("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(braces("x{,y}"), ["x", "xy"]);
+    }
+
+    #[test]
+    fn left_alone() {
+        assert_eq!(braces("plain"), ["plain"]);
+        assert_eq!(braces("{}"), ["{}"]);
+        assert_eq!(braces("{a}"), ["{a}"]);
+        assert_eq!(braces("{a,b"), ["{a,b"]);
+        assert_eq!(braces("{1..x}"), ["{1..x}"]);
+    }
+
+    #[test]
+    fn quoted_commas_do_not_split() {
+        let word = WordNode {
+            segments: vec![
+                Segment::Unquoted("{".to_string()),
+                Segment::SingleQuoted("a,b".to_string()),
+                Segment::Unquoted(",c}".to_string()),
+            ],
+        };
+        assert_eq!(words(&word), ["a,b", "c"]);
+    }
+
+    #[test]
+    fn escaped_braces_do_not_expand() {
+        assert_eq!(parsed("\\{1..2\\}a\\{b,c}"), ["{1..2}a{b,c}"]);
+        assert_eq!(parsed("{a\\,b,c}"), ["a,b", "c"]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(braces("{1..4}"), ["1", "2", "3", "4"]);
+        assert_eq!(braces("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(braces("{0..10..5}"), ["0", "5", "10"]);
+        assert_eq!(braces("{1..6..-2}"), ["1", "3", "5"]);
+        assert_eq!(braces("{-2..1}"), ["-2", "-1", "0", "1"]);
+        assert_eq!(braces("{08..10}"), ["08", "09", "10"]);
+        assert_eq!(braces("{-05..-03}"), ["-05", "-04", "-03"]);
+        assert_eq!(braces("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(braces("{C..A}"), ["C", "B", "A"]);
+    }
+
+    #[test]
+    fn overflowing_or_huge_ranges_stay_unexpanded() {
+        let full = format!("{{{}..{}}}", i64::MIN, i64::MAX);
+        assert_eq!(braces(&full), [full]);
+        let step = format!("{{0..10..{}}}", i64::MIN);
+        assert_eq!(braces(&step), [step]);
+        assert_eq!(braces("{1..1000000}"), ["{1..1000000}"]);
+        assert_eq!(braces("{1..100000}").len(), MAX_RANGE_LEN as usize);
+    }
+}
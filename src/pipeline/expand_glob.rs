@@ -0,0 +1,197 @@
+// パス名展開（グロブ）。パターンを `/` で区切り、ディレクトリを 1 段ずつたどって照合する。
+// クォートされた部分は呼び出し側で `\` エスケープ済み（pattern::escape）なので、そのまま文字として扱われる。
+use std::{fs, os::unix::fs::PermissionsExt, time::SystemTime};
+
+use super::pattern;
+use crate::shell::ShellOptions;
+
+/// pattern に一致するパスをソートして返す。1 つも一致しなければ空
+pub fn expand_glob(pattern: &str, options: &ShellOptions) -> Vec<String> {
+    let (pattern, qualifiers) = split_qualifiers(pattern);
+    // 末尾の `/` はディレクトリだけに絞る指定
+    let dirs_only = pattern.len() > 1 && pattern.ends_with('/');
+    let body = pattern.trim_end_matches('/');
+    let (root, body) = match body.strip_prefix('/') {
+        Some(rest) => ("/".to_string(), rest),
+        None => (String::new(), body),
+    };
+
+    let components: Vec<&str> = body.split('/').filter(|c| !c.is_empty()).collect();
+    let mut paths = vec![root];
+    for (i, comp) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut next = Vec::new();
+        for prefix in &paths {
+            if *comp == "**" {
+                walk(prefix, last, options, &mut next);
+            } else if pattern::has_wildcard(comp) {
+                for name in read_names(prefix) {
+                    if is_hidden(&name, comp, options) || !pattern::matches(comp, &name) {
+                        continue;
+                    }
+                    let path = format!("{prefix}{name}");
+                    if last {
+                        next.push(path);
+                    } else if is_dir(&path) {
+                        next.push(path + "/");
+                    }
+                }
+            } else {
+                // ワイルドカードのない部分はそのままつなぐ（存在確認は最後にまとめて行う）
+                let mut path = format!("{prefix}{}", pattern::unescape(comp));
+                if !last {
+                    path.push('/');
+                }
+                next.push(path);
+            }
+        }
+        paths = next;
+    }
+
+    let mut result: Vec<String> = paths
+        .into_iter()
+        .filter(|p| !p.is_empty() && fs::symlink_metadata(p).is_ok())
+        .filter(|p| !dirs_only || is_dir(p))
+        .filter(|p| qualifiers.iter().all(|q| q.test(p.trim_end_matches('/'))))
+        .map(|p| {
+            let p = p.trim_end_matches('/').to_string();
+            if dirs_only { p + "/" } else { p }
+        })
+        .collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// `**`: prefix 以下を再帰的にたどる。途中なら prefix 自身を含むディレクトリを、
+/// 最後の要素ならすべてのファイルとディレクトリを集める
+fn walk(prefix: &str, last: bool, options: &ShellOptions, out: &mut Vec<String>) {
+    if !last {
+        out.push(prefix.to_string());
+    }
+    for name in read_names(prefix) {
+        if is_hidden(&name, "**", options) {
+            continue;
+        }
+        let path = format!("{prefix}{name}");
+        // シンボリックリンクのディレクトリはループを避けるためたどらない
+        let is_real_dir = fs::symlink_metadata(&path)
+            .map(|m| m.is_dir())
+            .unwrap_or(false);
+        if last {
+            out.push(path.clone());
+        }
+        if is_real_dir {
+            walk(&(path + "/"), last, options, out);
+        }
+    }
+}
+
+/// ドットファイルは、パターン自体が `.` で始まるか dotglob が有効なときだけ一致させる
+fn is_hidden(name: &str, comp: &str, options: &ShellOptions) -> bool {
+    name.starts_with('.') && !options.dotglob && !comp.starts_with('.') && !comp.starts_with("\\.")
+}
+
+fn read_names(prefix: &str) -> Vec<String> {
+    let dir = if prefix.is_empty() { "." } else { prefix };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect()
+}
+
+fn is_dir(path: &str) -> bool {
+    fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false)
+}
+
+/// zsh 風の修飾子。パターン末尾の `(...)` で指定し、複数並べるとすべてを満たすものに絞る
+enum Qualifier {
+    File,                     // `.` 通常ファイル
+    Dir,                      // `/` ディレクトリ
+    Executable,               // `*` 実行可能な通常ファイル
+    Symlink,                  // `@` シンボリックリンク
+    Modified(char, u64, u64), // `m[Mwhms][-+]N` 更新からの経過（比較, 単位秒数, N）
+}
+
+impl Qualifier {
+    fn test(&self, path: &str) -> bool {
+        let meta = fs::metadata(path).ok();
+        match self {
+            Qualifier::File => meta.is_some_and(|m| m.is_file()),
+            Qualifier::Dir => meta.is_some_and(|m| m.is_dir()),
+            Qualifier::Executable => {
+                meta.is_some_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            }
+            Qualifier::Symlink => fs::symlink_metadata(path)
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false),
+            Qualifier::Modified(cmp, unit, n) => {
+                let Some(age) = meta
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|t| SystemTime::now().duration_since(t).ok())
+                    .map(|d| d.as_secs() / unit)
+                else {
+                    return false;
+                };
+                match cmp {
+                    '-' => age < *n,
+                    '+' => age > *n,
+                    _ => age == *n,
+                }
+            }
+        }
+    }
+}
+
+/// 末尾の `(...)` が修飾子として読めれば切り離す。`*.(rs|toml)` や `!(...)` はそのまま
+fn split_qualifiers(pattern: &str) -> (&str, Vec<Qualifier>) {
+    let Some(body) = pattern.strip_suffix(')') else {
+        return (pattern, Vec::new());
+    };
+    let Some(open) = body.rfind('(') else {
+        return (pattern, Vec::new());
+    };
+    let (head, quals) = (&body[..open], &body[open + 1..]);
+    if head.is_empty() || head.ends_with('!') || head.ends_with('\\') {
+        return (pattern, Vec::new());
+    }
+    match parse_qualifiers(quals) {
+        Some(list) => (head, list),
+        None => (pattern, Vec::new()),
+    }
+}
+
+fn parse_qualifiers(s: &str) -> Option<Vec<Qualifier>> {
+    let mut list = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let q = match c {
+            '.' => Qualifier::File,
+            '/' => Qualifier::Dir,
+            '*' => Qualifier::Executable,
+            '@' => Qualifier::Symlink,
+            'm' => {
+                let unit = match chars.next_if(|c| "Mwhms".contains(*c)) {
+                    Some('M') => 30 * 86400,
+                    Some('w') => 7 * 86400,
+                    Some('h') => 3600,
+                    Some('m') => 60,
+                    Some('s') => 1,
+                    _ => 86400,
+                };
+                let cmp = chars.next_if(|c| *c == '-' || *c == '+').unwrap_or('=');
+                let mut digits = String::new();
+                while let Some(d) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                Qualifier::Modified(cmp, unit, digits.parse().ok()?)
+            }
+            _ => return None,
+        };
+        list.push(q);
+    }
+    if list.is_empty() { None } else { Some(list) }
+}
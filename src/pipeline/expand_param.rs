@@ -0,0 +1,203 @@
+use super::{
+    expand_word::expand_word_joined,
+    parse::{Param, ParamOp, WordNode, variable_value},
+    pattern,
+};
+use crate::{
+    error::{Error, Result},
+    shell::Shell,
+};
+
+/// パラメータ展開（`${VAR:=word}` の代入や `${VAR:?msg}` のエラーを含む）
+pub fn expand_param(param: &Param, shell: &mut Shell) -> Result<String> {
+    let value = variable_value(&param.name, shell);
+    match &param.op {
+        ParamOp::Assign(word) if value.is_empty() => {
+            let value = expand_word_joined(word, shell)?;
+            shell.variables.set(&param.name, value.clone());
+            Ok(value)
+        }
+        ParamOp::Required(word) if value.is_empty() => {
+            let msg = expand_word_joined(word, shell)?;
+            let msg = if msg.is_empty() {
+                "parameter null or not set".to_string()
+            } else {
+                msg
+            };
+            Err(Error::Expansion(format!("{}: {}", param.name, msg)))
+        }
+        op => apply(op, value, |w| expand_word_joined(w, shell)),
+    }
+}
+
+/// 表示用（補完など）の展開。代入やコマンド置換の実行はしない
+pub fn param_text(param: &Param, shell: &Shell) -> String {
+    let value = variable_value(&param.name, shell);
+    apply(&param.op, value, |w| Ok(w.concat_text(shell))).unwrap_or_default()
+}
+
+fn apply(
+    op: &ParamOp,
+    value: String,
+    mut word: impl FnMut(&WordNode) -> Result<String>,
+) -> Result<String> {
+    Ok(match op {
+        ParamOp::Value | ParamOp::Required(_) => value,
+        ParamOp::Length => value.chars().count().to_string(),
+        ParamOp::Default(w) | ParamOp::Assign(w) => {
+            if value.is_empty() {
+                word(w)?
+            } else {
+                value
+            }
+        }
+        ParamOp::TrimPrefix(pat, longest) => trim_prefix(&value, &word(pat)?, *longest),
+        ParamOp::TrimSuffix(pat, longest) => trim_suffix(&value, &word(pat)?, *longest),
+        ParamOp::Replace(pat, rep, all) => replace(&value, &word(pat)?, &word(rep)?, *all),
+    })
+}
+
+/// 文字境界のバイト位置（先頭と末尾を含む）
+fn boundaries(s: &str) -> Vec<usize> {
+    s.char_indices().map(|(i, _)| i).chain([s.len()]).collect()
+}
+
+fn trim_prefix(value: &str, pat: &str, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    match ends
+        .into_iter()
+        .find(|&i| pattern::matches(pat, &value[..i]))
+    {
+        Some(i) => value[i..].to_string(),
+        None => value.to_string(),
+    }
+}
+
+fn trim_suffix(value: &str, pat: &str, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    match starts
+        .into_iter()
+        .find(|&i| pattern::matches(pat, &value[i..]))
+    {
+        Some(i) => value[..i].to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// 左から最長一致で置換する。all なら重ならない一致すべて
+fn replace(value: &str, pat: &str, rep: &str, all: bool) -> String {
+    if pat.is_empty() {
+        return value.to_string();
+    }
+    let bounds = boundaries(value);
+    let mut out = String::new();
+    let mut copied = 0; // value のうち out へ書き出し済みの位置
+    let mut k = 0;
+    while k < bounds.len() {
+        let start = bounds[k];
+        let end = bounds[k + 1..]
+            .iter()
+            .rev()
+            .copied()
+            .find(|&end| pattern::matches(pat, &value[start..end]));
+        match end {
+            Some(end) => {
+                out.push_str(&value[copied..start]);
+                out.push_str(rep);
+                copied = end;
+                if !all {
+                    break;
+                }
+                k = bounds
+                    .iter()
+                    .position(|&b| b == end)
+                    .unwrap_or(bounds.len());
+            }
+            None => k += 1,
+        }
+    }
+    out.push_str(&value[copied..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::parse::Segment;
+
+    fn word(text: &str) -> WordNode {
+        WordNode {
+            segments: vec![Segment::Unquoted(text.to_string())],
+        }
+    }
+
+    /// op を value に適用する。word はそのままの文字列として展開する
+    fn eval(op: &ParamOp, value: &str) -> String {
+        apply(op, value.to_string(), |w| match &w.segments[..] {
+            [Segment::Unquoted(t)] => Ok(t.clone()),
+            _ => unreachable!(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn value_and_length() {
+        assert_eq!(eval(&ParamOp::Value, "abc"), "abc");
+        assert_eq!(eval(&ParamOp::Length, "あいう"), "3");
+        assert_eq!(eval(&ParamOp::Length, ""), "0");
+    }
+
+    #[test]
+    fn defaults_apply_only_to_empty_values() {
+        assert_eq!(eval(&ParamOp::Default(word("dflt")), ""), "dflt");
+        assert_eq!(eval(&ParamOp::Default(word("dflt")), "v"), "v");
+        assert_eq!(eval(&ParamOp::Assign(word("set")), ""), "set");
+        assert_eq!(eval(&ParamOp::Assign(word("set")), "v"), "v");
+        assert_eq!(eval(&ParamOp::Required(word("msg")), "v"), "v");
+    }
+
+    #[test]
+    fn operators_expand_their_words() {
+        let v = "/usr/local/lib.tar.gz";
+        assert_eq!(
+            eval(&ParamOp::TrimPrefix(word("*/"), true), v),
+            "lib.tar.gz"
+        );
+        assert_eq!(
+            eval(&ParamOp::TrimSuffix(word(".*"), false), v),
+            "/usr/local/lib.tar"
+        );
+        assert_eq!(
+            eval(&ParamOp::Replace(word("/"), word(":"), true), v),
+            ":usr:local:lib.tar.gz"
+        );
+    }
+
+    #[test]
+    fn trimming() {
+        let v = "/usr/local/lib.tar.gz";
+        assert_eq!(trim_prefix(v, "*/", false), "usr/local/lib.tar.gz");
+        assert_eq!(trim_prefix(v, "*/", true), "lib.tar.gz");
+        assert_eq!(trim_suffix(v, ".*", false), "/usr/local/lib.tar");
+        assert_eq!(trim_suffix(v, ".*", true), "/usr/local/lib");
+        assert_eq!(trim_prefix(v, "x*", true), v);
+        assert_eq!(trim_suffix("あいう", "う", false), "あい");
+    }
+
+    #[test]
+    fn replacing() {
+        assert_eq!(replace("a-b-c", "-", "+", false), "a+b-c");
+        assert_eq!(replace("a-b-c", "-", "+", true), "a+b+c");
+        // 左から最長一致
+        assert_eq!(replace("aXbXc", "X*", "_", false), "a_");
+        assert_eq!(replace("aaa", "a", "", true), "");
+        assert_eq!(replace("abc", "", "x", true), "abc");
+        assert_eq!(replace("ああい", "あ", "う", true), "ううい");
+    }
+}
@@ -0,0 +1,173 @@
+use super::{
+    arith::eval_arith,
+    execute::{capture_output, spawn_proc_subst},
+    expand_glob::expand_glob,
+    expand_param::expand_param,
+    parse::{ParamOp, Segment, WordNode, tilde_value, variable_list},
+    pattern,
+};
+use crate::{
+    error::{Error, Result},
+    shell::{NoMatch, Shell},
+};
+
+/// 展開途中の 1 引数。pattern はクォートされた部分などを `\` でエスケープした、グロブ照合用の文字列
+#[derive(Clone, Default)]
+struct Field {
+    text: String,
+    pattern: String,
+}
+
+/// WordNode を引数列へ展開する（コマンド置換を実行する）
+/// クォートされていないコマンド置換は行ごとに別の引数となり、前後の文字列とは直積で結合される。
+pub fn expand_word(word: &WordNode, shell: &mut Shell) -> Result<Vec<String>> {
+    let (fields, _) = expand_fields(word, shell)?;
+    Ok(fields.into_iter().map(|f| f.text).collect())
+}
+
+/// expand_word に加えてパス名展開も行う。ワイルドカードとして働くのはクォートされていない部分だけ
+pub fn expand_word_glob(word: &WordNode, shell: &mut Shell) -> Result<Vec<String>> {
+    let (fields, glob) = expand_fields(word, shell)?;
+    if !glob {
+        return Ok(fields.into_iter().map(|f| f.text).collect());
+    }
+    let mut args = Vec::new();
+    for field in fields {
+        let mut matches = expand_glob(&field.pattern, &shell.options);
+        if !matches.is_empty() {
+            args.append(&mut matches);
+            continue;
+        }
+        match shell.options.nomatch {
+            NoMatch::Error => return Err(Error::NoMatch(field.text)),
+            NoMatch::Passthrough => args.push(field.text),
+            NoMatch::Null => {}
+        }
+    }
+    Ok(args)
+}
+
+/// リダイレクト先など、単一の文字列が必要な場面での展開
+pub fn expand_word_joined(word: &WordNode, shell: &mut Shell) -> Result<String> {
+    Ok(expand_word(word, shell)?.join(" "))
+}
+
+/// 戻り値の bool はクォートされていないワイルドカードを含むか
+fn expand_fields(word: &WordNode, shell: &mut Shell) -> Result<(Vec<Field>, bool)> {
+    let mut fields = vec![Field::default()];
+    let mut glob = false;
+    for seg in &word.segments {
+        match seg {
+            Segment::Unquoted(t) => {
+                glob |= pattern::has_wildcard(t);
+                push_all(&mut fields, t, t);
+            }
+            Segment::DoubleQuoted(t) | Segment::SingleQuoted(t) => {
+                push_all(&mut fields, t, &pattern::escape(t));
+            }
+            Segment::Variable { param, quoted } => {
+                if !quoted
+                    && matches!(param.op, ParamOp::Value)
+                    && let Some(items) = variable_list(&param.name, shell)
+                {
+                    fields = push_each(&fields, &items);
+                    continue;
+                }
+                let val = expand_param(param, shell)?;
+                push_all(&mut fields, &val, &pattern::escape(&val));
+            }
+            Segment::Tilde(prefix) => {
+                let dir = tilde_value(prefix, shell);
+                push_all(&mut fields, &dir, &pattern::escape(&dir));
+            }
+            Segment::ProcSubst { expr, input } => {
+                let path = spawn_proc_subst(expr, *input, shell)?;
+                push_all(&mut fields, &path, &pattern::escape(&path));
+            }
+            Segment::Arith(expr) => {
+                let n = eval_arith(expr, shell)
+                    .map_err(|msg| Error::Expansion(format!("$(({expr})): {msg}")))?
+                    .to_string();
+                push_all(&mut fields, &n, &n);
+            }
+            Segment::Command { expr, quoted } => {
+                let out = capture_output(expr, shell)?;
+                let out = out.trim_end_matches('\n');
+                if *quoted {
+                    push_all(&mut fields, out, &pattern::escape(out));
+                    continue;
+                }
+                let lines: Vec<&str> = if out.is_empty() {
+                    vec![]
+                } else {
+                    out.split('\n').collect()
+                };
+                fields = push_each(&fields, &lines);
+            }
+        }
+    }
+    Ok((fields, glob))
+}
+
+fn push_all(fields: &mut [Field], text: &str, pattern: &str) {
+    for f in fields.iter_mut() {
+        f.text.push_str(text);
+        f.pattern.push_str(pattern);
+    }
+}
+
+/// 各引数の後ろに items をそれぞれつなげた組み合わせ（items が空なら引数は消える）
+fn push_each(fields: &[Field], items: &[impl AsRef<str>]) -> Vec<Field> {
+    fields
+        .iter()
+        .flat_map(|f| {
+            items.iter().map(move |item| Field {
+                text: format!("{}{}", f.text, item.as_ref()),
+                pattern: format!("{}{}", f.pattern, pattern::escape(item.as_ref())),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::pipeline::{
+        parse::{Expr, parse},
+        tokenize::tokenize,
+    };
+
+    /// src を 1 語のコマンドとして構文解析し、パス名展開まで行う
+    fn expand(src: &str) -> Vec<String> {
+        let Expr::Pipe(cmds) = parse(&tokenize(src)).unwrap() else {
+            unreachable!()
+        };
+        expand_word_glob(&cmds[0].cmd_name, &mut Shell::new(false)).unwrap()
+    }
+
+    #[test]
+    fn escaped_wildcards_are_literal() {
+        let dir = std::env::temp_dir().join(format!("my_shell_glob_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.rs", "b.rs"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let d = dir.display();
+
+        assert_eq!(
+            expand(&format!("{d}/*.rs")),
+            [format!("{d}/a.rs"), format!("{d}/b.rs")]
+        );
+        assert_eq!(expand(&format!("{d}/\\*.rs")), [format!("{d}/*.rs")]);
+        assert_eq!(expand(&format!("{d}/?.rs")).len(), 2);
+        assert_eq!(expand(&format!("{d}/\\?.rs")), [format!("{d}/?.rs")]);
+        assert_eq!(expand(&format!("{d}/[ab].rs")).len(), 2);
+        assert_eq!(expand(&format!("{d}/\\[ab].rs")), [format!("{d}/[ab].rs")]);
+        // エスケープしていない部分はワイルドカードのまま
+        assert_eq!(expand(&format!("{d}/\\a*")), [format!("{d}/a.rs")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
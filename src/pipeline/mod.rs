@@ -1,11 +1,18 @@
+mod arith;
 mod execute;
 mod expand_abbr;
 mod expand_alias;
+mod expand_brace;
+mod expand_glob;
+mod expand_param;
+mod expand_word;
 mod parse;
+mod pattern;
 mod tokenize;
 
-pub(super) use execute::execute;
+pub(super) use arith::eval_arith;
+pub(super) use execute::{execute, execute_argv};
 pub(super) use expand_abbr::expand_abbr;
 pub(super) use expand_alias::expand_aliases;
-pub(super) use parse::parse;
-pub(super) use tokenize::{tokenize, tokens_to_string};
+pub(super) use parse::{is_var_name, parse, statements};
+pub(super) use tokenize::{has_pending_heredoc, line_col, tokenize, tokens_to_string};
@@ -1,7 +1,11 @@
-use std::env;
+use std::{
+    env,
+    ffi::{CStr, CString},
+};
 
-use super::tokenize::{QuoteKind, Token};
+use super::tokenize::{QuoteKind, Token, Tokens, tokenize, tokenize_heredoc, tokens_to_string};
 
+use super::{arith::eval_arith, expand_param::param_text};
 use crate::{
     error::{Error, Result},
     shell::Shell,
@@ -12,7 +16,30 @@ pub enum Segment {
     Unquoted(String),
     DoubleQuoted(String),
     SingleQuoted(String),
-    Variable(String),
+    Variable { param: Param, quoted: bool },
+    Tilde(String), // `~` の後ろ（`~alice` なら "alice"）
+    Arith(String), // $(( expr )) の式
+    Command { expr: Box<Expr>, quoted: bool },
+    ProcSubst { expr: Box<Expr>, input: bool }, // input は <(cmd)（cmd の出力を読む側）
+}
+
+/// `$VAR` / `${...}` の中身
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub op: ParamOp,
+}
+
+#[derive(Debug, Clone)]
+pub enum ParamOp {
+    Value,                             // $VAR / ${VAR}
+    Length,                            // ${#VAR}
+    Default(WordNode),                 // ${VAR:-word}
+    Assign(WordNode),                  // ${VAR:=word}
+    Required(WordNode),                // ${VAR:?msg}
+    TrimPrefix(WordNode, bool),        // ${VAR#pat} / ${VAR##pat}（true は最長一致）
+    TrimSuffix(WordNode, bool),        // ${VAR%pat} / ${VAR%%pat}
+    Replace(WordNode, WordNode, bool), // ${VAR/pat/rep} / ${VAR//pat/rep}（true は全置換）
 }
 
 #[derive(Debug, Clone)]
@@ -34,37 +61,125 @@ impl WordNode {
                 Segment::Unquoted(t) | Segment::DoubleQuoted(t) | Segment::SingleQuoted(t) => {
                     s.push_str(t);
                 }
-                Segment::Variable(t) => {
-                    if let Some(val) = shell.variables.get(t) {
-                        s.push_str(val);
-                        continue;
-                    }
-                    if let Ok(val) = env::var(t) {
-                        s.push_str(&val);
-                        continue;
+                Segment::Variable { param, .. } => s.push_str(&param_text(param, shell)),
+                Segment::Tilde(prefix) => s.push_str(&tilde_value(prefix, shell)),
+                Segment::Arith(expr) => {
+                    if let Ok(n) = eval_arith(expr, shell) {
+                        s.push_str(&n.to_string());
                     }
                 }
+                // 補完などの表示用途では実行しない
+                Segment::Command { .. } | Segment::ProcSubst { .. } => {}
             }
         }
         s
     }
 }
 
-#[derive(Debug)]
+/// 要素ごとに別の引数に展開する特殊変数（クォートしなければ `$pipestatus` は段ごとに 1 引数）
+pub fn variable_list(name: &str, shell: &Shell) -> Option<Vec<String>> {
+    match name {
+        "pipestatus" => Some(shell.pipestatus.iter().map(|c| c.to_string()).collect()),
+        _ => None,
+    }
+}
+
+/// 変数の値（未定義なら空文字列）。シェルが管理する特殊変数を先に見る
+pub fn variable_value(name: &str, shell: &Shell) -> String {
+    match name {
+        "?" | "status" => return shell.status.to_string(),
+        "$" => return shell.pid.to_string(),
+        "PWD" => {
+            if let Ok(dir) = env::current_dir() {
+                return dir.display().to_string();
+            }
+        }
+        "OLDPWD" => {
+            if let Some(dir) = &shell.oldpwd {
+                return dir.display().to_string();
+            }
+        }
+        "pipestatus" => return variable_list(name, shell).unwrap_or_default().join(" "),
+        // 位置パラメータ（fish 風に $argv でまとめても引ける）
+        "argv" => return shell.args.get(1..).unwrap_or_default().join(" "),
+        n if n.chars().all(|c| c.is_ascii_digit()) => {
+            let arg = n.parse().ok().and_then(|i: usize| shell.args.get(i));
+            return arg.cloned().unwrap_or_default();
+        }
+        _ => {}
+    }
+    shell.variables.get(name).unwrap_or_default().to_string()
+}
+
+/// チルダ展開。展開できない `~nobody` などはそのまま返す
+pub fn tilde_value(prefix: &str, shell: &Shell) -> String {
+    let dir = match prefix {
+        "" => Some(variable_value("HOME", shell)),
+        "+" => Some(variable_value("PWD", shell)),
+        "-" => shell.oldpwd.as_ref().map(|d| d.display().to_string()),
+        _ if prefix
+            .trim_start_matches(['+', '-'])
+            .parse::<usize>()
+            .is_ok() =>
+        {
+            dir_stack_entry(prefix, shell)
+        }
+        user => home_of(user),
+    };
+    dir.unwrap_or_else(|| format!("~{prefix}"))
+}
+
+/// `~N` `~+N` は `dirs` の左（0 がカレント、1 が直前）から、`~-N` は右から数える
+fn dir_stack_entry(prefix: &str, shell: &Shell) -> Option<String> {
+    let mut dirs = vec![env::current_dir().ok()?];
+    dirs.extend(shell.dir_stack.iter().rev().cloned());
+    let n: usize = prefix.trim_start_matches(['+', '-']).parse().ok()?;
+    let idx = if prefix.starts_with('-') {
+        dirs.len().checked_sub(n + 1)?
+    } else {
+        n
+    };
+    dirs.get(idx).map(|d| d.display().to_string())
+}
+
+/// passwd データベースからユーザーのホームディレクトリを引く
+fn home_of(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    unsafe {
+        let pw = libc::getpwnam(name.as_ptr());
+        if pw.is_null() || (*pw).pw_dir.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr((*pw).pw_dir).to_string_lossy().into_owned())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Expr {
     And(Box<Expr>, Box<Expr>),
     Or(Box<Expr>, Box<Expr>),
     Pipe(Vec<CommandExpr>),
+    Seq(Vec<Expr>),                // `;` や改行で区切られた列
+    Background(Box<Expr>, String), // `cmd &`（ジョブ表示用のコマンド文字列つき）
+    Not(Box<Expr>),                // `! cmd` / `not cmd`
 }
 
 impl Expr {
-    pub fn last_cmd_expr(&self) -> CommandExpr {
+    pub fn last_cmd_expr(&self) -> Option<CommandExpr> {
         let mut expr = self;
         loop {
             match expr {
                 Expr::And(_, b) => expr = b,
                 Expr::Or(_, b) => expr = b,
-                Expr::Pipe(a) => return a.last().unwrap().clone(),
+                Expr::Pipe(a) => {
+                    let last = a.last()?;
+                    match &last.group {
+                        Some(Group::Current(e, _) | Group::Subshell(e, _)) => expr = e,
+                        None => return Some(last.clone()),
+                    }
+                }
+                Expr::Seq(list) => expr = list.last()?,
+                Expr::Background(e, _) | Expr::Not(e) => expr = e,
             }
         }
     }
@@ -72,61 +187,211 @@ impl Expr {
 
 #[derive(Debug, Clone)]
 pub struct CommandExpr {
+    pub assignments: Vec<(String, WordNode)>, // 先頭の NAME=value（このコマンドだけの環境変数）
     pub cmd_name: WordNode,
     pub args: Vec<WordNode>,
-    pub stdout: Redirection,
-    pub stderr: Redirection,
+    pub pipe: PipeTo,                // 次の段へつなぐ出力
+    pub redirects: Vec<Redirection>, // 記述順に適用する
+    pub group: Option<Group>,        // Some ならコマンドの代わりにグループを実行する
+}
+
+#[derive(Debug, Clone)]
+pub enum Group {
+    Current(Box<Expr>, String),  // { a; b }  今のシェルで実行
+    Subshell(Box<Expr>, String), // ( a; b )  fork した子で実行
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeTo {
+    None,
+    Stdout, // |
+    Stderr, // 2|
+    Both,   // &|
 }
 
 #[derive(Debug, Clone)]
-pub enum Redirection {
-    Pipe,
-    Inherit,
-    File { path: WordNode, append: bool },
+pub struct Redirection {
+    pub fd: i32,
+    pub target: RedirectTarget,
 }
 
-pub fn parse(tokens: &[Token]) -> Result<Expr> {
-    let expr = parse_expr(tokens, 0)?.0;
+#[derive(Debug, Clone)]
+pub enum RedirectTarget {
+    File { path: WordNode, append: bool }, // n> file / n>> file
+    Input(WordNode),                       // n< file
+    HereDoc(WordNode),                     // <<EOF ... EOF（本文）
+    HereString(WordNode),                  // <<< word
+    Dup(i32),                              // n>&m / n<&m
+    Close,                                 // n>&-
+}
+
+pub fn parse(tokens: &Tokens) -> Result<Expr> {
+    if let Some((quote, at)) = tokens.unclosed_quote {
+        let e = Error::UnterminatedQuote { quote, at };
+        return Err(Error::Incomplete(Box::new(e)));
+    }
+    if tokens.line_continued {
+        return Err(unexpected(tokens, tokens.len()));
+    }
+    let mut i = 0;
+    let expr = parse_list(tokens, &mut i, false)?;
+    if i < tokens.len() {
+        return Err(unexpected(tokens, i));
+    }
     Ok(expr)
 }
 
-fn parse_expr(tokens: &[Token], mut i: usize) -> Result<(Expr, usize)> {
-    let mut lhs = parse_pipe(tokens, &mut i)?;
-    while let Some(token) = skip_delimiter_get(tokens, &mut i) {
+/// `;` や改行、`&` で区切られた列。in_group なら閉じ `}` の手前で止まる
+fn parse_list(tokens: &Tokens, i: &mut usize, in_group: bool) -> Result<Expr> {
+    let mut list = Vec::new();
+    loop {
+        while matches!(
+            tokens.get(*i),
+            Some(Token::Semicolon | Token::Newline | Token::Delimiter)
+        ) {
+            *i += 1;
+        }
+        if *i >= tokens.len() || (in_group && is_close_brace(tokens, *i)) {
+            break;
+        }
+        list.push(parse_statement(tokens, i, in_group)?);
+    }
+    if list.len() == 1 {
+        Ok(list.remove(0))
+    } else {
+        Ok(Expr::Seq(list))
+    }
+}
+
+/// 列の要素 1 つ（`&&` / `||` でつないだもの）。後ろの `&` まで読み、`;` や改行は残す
+fn parse_statement(tokens: &Tokens, i: &mut usize, in_group: bool) -> Result<Expr> {
+    let start = *i;
+    let expr = parse_expr(tokens, i, in_group)?;
+    match skip_delimiter_get(tokens, i).cloned() {
+        None | Some(Token::Semicolon | Token::Newline) => Ok(expr),
+        Some(Token::Background) => {
+            let text = tokens_to_string(&tokens[start..*i]).trim().to_string();
+            *i += 1;
+            Ok(Expr::Background(Box::new(expr), text))
+        }
+        Some(_) if in_group && is_close_brace(tokens, *i) => Ok(expr),
+        Some(_) => Err(unexpected(tokens, *i)),
+    }
+}
+
+/// トップレベルの文を 1 つずつ解析する。構文エラーの文はエラーを返して読み飛ばし、
+/// その次の行から続ける（source でファイルの途中の書き間違いが残りを巻き込まないように）
+pub fn statements(tokens: &Tokens) -> Statements<'_> {
+    Statements { tokens, i: 0 }
+}
+
+pub struct Statements<'a> {
+    tokens: &'a Tokens,
+    i: usize,
+}
+
+impl Iterator for Statements<'_> {
+    type Item = Result<Expr>;
+
+    fn next(&mut self) -> Option<Result<Expr>> {
+        let tokens = self.tokens;
+        while matches!(
+            tokens.get(self.i),
+            Some(Token::Semicolon | Token::Newline | Token::Delimiter)
+        ) {
+            self.i += 1;
+        }
+        if self.i >= tokens.len() {
+            return None;
+        }
+        let start = tokens.span(self.i).start;
+        let error = match parse_statement(tokens, &mut self.i, false) {
+            Ok(expr) if self.i < tokens.len() => return Some(Ok(expr)),
+            // 入力の終わりまで読んだ文が、閉じていないクォートや行継続の `\` で終わっている
+            Ok(expr) => match tokens.unclosed_quote {
+                Some((quote, at)) => Error::UnterminatedQuote { quote, at },
+                None if tokens.line_continued => unexpected(tokens, tokens.len()),
+                None => return Some(Ok(expr)),
+            },
+            Err(e) => e,
+        };
+        // エラーの位置より後ろの最初の改行まで飛ばす
+        let at = error.position().unwrap_or(start);
+        while let Some(token) = tokens.get(self.i)
+            && (tokens.span(self.i).start < at || *token != Token::Newline)
+        {
+            self.i += 1;
+        }
+        Some(Err(error))
+    }
+}
+
+fn parse_expr(tokens: &Tokens, i: &mut usize, in_group: bool) -> Result<Expr> {
+    let mut lhs = parse_pipe(tokens, i, in_group)?;
+    while let Some(token) = skip_delimiter_get(tokens, i) {
         match token {
             Token::And => {
-                i += 1;
-                let rhs = parse_pipe(tokens, &mut i)?;
+                *i += 1;
+                skip_newlines(tokens, i);
+                let rhs = parse_pipe(tokens, i, in_group)?;
                 lhs = Expr::And(Box::new(lhs), Box::new(rhs));
             }
             Token::Or => {
-                i += 1;
-                let rhs = parse_pipe(tokens, &mut i)?;
+                *i += 1;
+                skip_newlines(tokens, i);
+                let rhs = parse_pipe(tokens, i, in_group)?;
                 lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
             }
             _ => break,
         }
     }
-    Ok((lhs, i))
+    Ok(lhs)
 }
 
-fn parse_pipe(tokens: &[Token], i: &mut usize) -> Result<Expr> {
-    let mut commands = vec![parse_command(tokens, i)?];
+fn parse_pipe(tokens: &Tokens, i: &mut usize, in_group: bool) -> Result<Expr> {
+    // パイプライン先頭の ! / not は終了コードを反転する
+    skip_delimiter_get(tokens, i);
+    if is_bare_word(tokens, *i, "!") || is_bare_word(tokens, *i, "not") {
+        *i += 1;
+        let inner = parse_pipe(tokens, i, in_group)?;
+        return Ok(Expr::Not(Box::new(inner)));
+    }
+    let mut commands = vec![parse_command(tokens, i, in_group)?];
     while let Some(token) = skip_delimiter_get(tokens, i) {
-        if !matches!(token, Token::Pipe) {
+        if !matches!(token, Token::Pipe | Token::PipeErr | Token::PipeBoth) {
             break;
         }
+        let pipe_at = tokens.span(*i).start;
         *i += 1;
-        commands.push(parse_command(tokens, i)?);
+        skip_newlines(tokens, i);
+        match tokens.get(*i) {
+            Some(Token::Word(..)) => {}
+            // `git log |` で終わっていれば続きの行を待つ
+            None => {
+                let e = Error::EmptyPipelineStage { at: pipe_at };
+                return Err(Error::Incomplete(Box::new(e)));
+            }
+            Some(_) => return Err(Error::EmptyPipelineStage { at: pipe_at }),
+        }
+        commands.push(parse_command(tokens, i, in_group)?);
     }
     Ok(Expr::Pipe(commands))
 }
 
+/// 単独の単語として `s` が置かれているか（`{` `}` はこのときだけ予約語）
+fn is_bare_word(tokens: &[Token], i: usize, s: &str) -> bool {
+    matches!(tokens.get(i), Some(Token::Word(w, QuoteKind::None)) if w == s)
+        && !matches!(tokens.get(i + 1), Some(Token::Word(..)))
+}
+
+fn is_close_brace(tokens: &[Token], i: usize) -> bool {
+    is_bare_word(tokens, i, "}")
+}
+
 /// 1 トークン＝1 WordNode（クォート種別を Segment に落とす）
-fn parse_word_node(tokens: &[Token], i: &mut usize) -> Result<WordNode> {
+fn parse_word_node(tokens: &Tokens, i: &mut usize) -> Result<WordNode> {
     let mut node = WordNode::new();
     while let Some(token) = tokens.get(*i) {
-        *i += 1;
         match token {
             Token::Word(s, QuoteKind::None) => node.segments.push(Segment::Unquoted(s.clone())),
             Token::Word(s, QuoteKind::Single) => {
@@ -135,80 +400,307 @@ fn parse_word_node(tokens: &[Token], i: &mut usize) -> Result<WordNode> {
             Token::Word(s, QuoteKind::Double) => {
                 node.segments.push(Segment::DoubleQuoted(s.clone()))
             }
-            Token::Word(s, QuoteKind::Variable) => node.segments.push(Segment::Variable(s.clone())),
-            Token::Word(_s, QuoteKind::Tilde) => {
-                node.segments.push(Segment::Variable("HOME".to_string()))
+            Token::Word(s, kind @ (QuoteKind::Variable | QuoteKind::QuotedVariable)) => {
+                node.segments.push(Segment::Variable {
+                    param: parse_param(s)?,
+                    quoted: *kind == QuoteKind::QuotedVariable,
+                })
+            }
+            Token::Word(s, QuoteKind::Tilde) => {
+                node.segments.push(Segment::Tilde(s[1..].to_string()))
+            }
+            Token::Word(s, QuoteKind::Arith) => node.segments.push(Segment::Arith(s.clone())),
+            Token::Word(s, QuoteKind::ProcSubst) => {
+                let expr = parse_subst(s, tokens.span(*i).start)?;
+                node.segments.push(Segment::ProcSubst {
+                    expr: Box::new(expr),
+                    input: s.starts_with('<'),
+                })
+            }
+            Token::Word(s, kind @ (QuoteKind::Command | QuoteKind::QuotedCommand)) => {
+                let expr = parse_subst(s, tokens.span(*i).start)?;
+                node.segments.push(Segment::Command {
+                    expr: Box::new(expr),
+                    quoted: *kind == QuoteKind::QuotedCommand,
+                })
             }
             _ => break,
         }
+        *i += 1;
     }
     Ok(node)
 }
 
-fn parse_command(tokens: &[Token], i: &mut usize) -> Result<CommandExpr> {
-    // 先頭はコマンド名
-    let cmd_name = match must_get(tokens, i)? {
-        Token::Word(_, _) => parse_word_node(tokens, i)?,
-        _ => unreachable!("command must start with a word"),
+/// `NAME` / `{...}` を Param にする
+fn parse_param(s: &str) -> Result<Param> {
+    let Some(inner) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) else {
+        return Ok(Param {
+            name: s.to_string(),
+            op: ParamOp::Value,
+        });
     };
+    let bad = || Error::Expansion(format!("${}: bad substitution", s));
+
+    // ${#VAR}
+    if let Some(name) = inner.strip_prefix('#')
+        && !name.is_empty()
+    {
+        if !is_param_name(name) {
+            return Err(bad());
+        }
+        return Ok(Param {
+            name: name.to_string(),
+            op: ParamOp::Length,
+        });
+    }
+
+    let name_len = match inner.chars().next() {
+        Some('?' | '$') => 1,
+        Some(c) if c.is_ascii_digit() => inner.chars().take_while(|c| c.is_ascii_digit()).count(),
+        _ => inner
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .count(),
+    };
+    let (name, rest) = inner.split_at(name_len);
+    if !is_param_name(name) {
+        return Err(bad());
+    }
+    let word = |w: &str| parse_word_node(&tokenize_heredoc(w), &mut 0);
+    let op = if rest.is_empty() {
+        ParamOp::Value
+    } else if let Some(w) = rest.strip_prefix(":-") {
+        ParamOp::Default(word(w)?)
+    } else if let Some(w) = rest.strip_prefix(":=") {
+        ParamOp::Assign(word(w)?)
+    } else if let Some(w) = rest.strip_prefix(":?") {
+        ParamOp::Required(word(w)?)
+    } else if let Some(w) = rest.strip_prefix("##") {
+        ParamOp::TrimPrefix(word(w)?, true)
+    } else if let Some(w) = rest.strip_prefix('#') {
+        ParamOp::TrimPrefix(word(w)?, false)
+    } else if let Some(w) = rest.strip_prefix("%%") {
+        ParamOp::TrimSuffix(word(w)?, true)
+    } else if let Some(w) = rest.strip_prefix('%') {
+        ParamOp::TrimSuffix(word(w)?, false)
+    } else if let Some(w) = rest.strip_prefix('/') {
+        let (all, w) = match w.strip_prefix('/') {
+            Some(w) => (true, w),
+            None => (false, w),
+        };
+        let (pat, rep) = w.split_once('/').unwrap_or((w, ""));
+        ParamOp::Replace(word(pat)?, word(rep)?, all)
+    } else {
+        return Err(bad());
+    };
+    Ok(Param {
+        name: name.to_string(),
+        op,
+    })
+}
+
+fn is_param_name(name: &str) -> bool {
+    matches!(name, "?" | "$")
+        || (!name.is_empty() && name.chars().all(|c| c.is_ascii_digit()))
+        || is_var_name(name)
+}
+
+/// 代入できる変数名（英字か _ で始まり、英数字と _ だけ）
+pub fn is_var_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `NAME=value` の単語を名前と値に分ける。= より前がクォートされていれば代入ではない
+fn split_assignment(word: &WordNode) -> Option<(String, WordNode)> {
+    let Some(Segment::Unquoted(first)) = word.segments.first() else {
+        return None;
+    };
+    let (name, value) = first.split_once('=')?;
+    if !is_var_name(name) {
+        return None;
+    }
+    let mut node = WordNode::new();
+    if !value.is_empty() {
+        node.segments.push(Segment::Unquoted(value.to_string()));
+    }
+    node.segments.extend(word.segments[1..].iter().cloned());
+    Some((name.to_string(), node))
+}
+
+/// `(cmd)` / `$(cmd)` / `<(cmd)` から中身の `cmd` を取り出す
+fn subst_body(s: &str) -> &str {
+    let s = s.trim_start_matches(['$', '<', '>']);
+    let s = s.strip_prefix('(').unwrap_or(s);
+    s.strip_suffix(')').unwrap_or(s)
+}
+
+/// コマンド置換などの中身を構文解析する。at はトークンの位置で、エラーの位置は外側の入力に合わせる
+fn parse_subst(s: &str, at: usize) -> Result<Expr> {
+    let offset = at + s.find('(').map_or(0, |p| p + 1);
+    // 閉じた置換の中で入力が途切れているのは、続きの行を待つのではなく誤り
+    parse(&tokenize(subst_body(s))).map_err(|e| {
+        let e = match e {
+            Error::Incomplete(e) => *e,
+            e => e,
+        };
+        e.shifted(offset)
+    })
+}
+
+fn parse_command(tokens: &Tokens, i: &mut usize, in_group: bool) -> Result<CommandExpr> {
+    // 先頭はコマンド名かグループ
+    let (cmd_name, group) = match must_get(tokens, i)?.clone() {
+        // ( a; b )  単独の (..) はサブシェル
+        Token::Word(s, QuoteKind::Command)
+            if s.starts_with('(') && !matches!(tokens.get(*i + 1), Some(Token::Word(..))) =>
+        {
+            let expr = parse_subst(&s, tokens.span(*i).start)?;
+            *i += 1;
+            (WordNode::new(), Some(Group::Subshell(Box::new(expr), s)))
+        }
+        // { a; b }
+        Token::Word(_, QuoteKind::None) if is_bare_word(tokens, *i, "{") => {
+            let start = *i;
+            *i += 1;
+            let expr = parse_list(tokens, i, true)?;
+            if !is_close_brace(tokens, *i) {
+                return Err(unexpected(tokens, *i));
+            }
+            *i += 1;
+            let text = tokens_to_string(&tokens[start..*i]);
+            (WordNode::new(), Some(Group::Current(Box::new(expr), text)))
+        }
+        Token::Word(_, _) => (parse_word_node(tokens, i)?, None),
+        // `| foo` のように段の中身がない
+        Token::Pipe | Token::PipeErr | Token::PipeBoth => {
+            return Err(Error::EmptyPipelineStage {
+                at: tokens.span(*i).start,
+            });
+        }
+        _ => return Err(unexpected(tokens, *i)),
+    };
+
+    // 先頭の NAME=value を取り除く。コマンドが続かなければシェル変数への代入になる
+    let mut cmd_name = cmd_name;
+    let mut assignments = Vec::new();
+    while let Some(assignment) = split_assignment(&cmd_name) {
+        assignments.push(assignment);
+        let word = matches!(skip_delimiter_get(tokens, i), Some(Token::Word(..)));
+        cmd_name = if word && !(in_group && is_close_brace(tokens, *i)) {
+            parse_word_node(tokens, i)?
+        } else {
+            WordNode::new()
+        };
+    }
 
     let mut args: Vec<WordNode> = Vec::new();
-    let mut stdout = Redirection::Inherit;
-    let mut stderr = Redirection::Inherit;
+    let mut pipe = PipeTo::None;
+    let mut redirects: Vec<Redirection> = Vec::new();
 
     while *i < tokens.len() {
         let Some(token) = skip_delimiter_get(tokens, i) else {
             break;
         };
-        match token {
-            // > / >>  (stdout)
-            Token::RedirectOut | Token::RedirectAppend => {
-                let append = matches!(must_get(tokens, i)?, Token::RedirectAppend);
+        match token.clone() {
+            // [n]> / [n]>>
+            Token::RedirectOut(fd) | Token::RedirectAppend(fd) => {
+                let append = matches!(token, Token::RedirectAppend(_));
                 *i += 1;
-                if matches!(must_get(tokens, i)?, Token::Word(_, _)) {
-                    let path = parse_word_node(tokens, i)?;
-                    stdout = Redirection::File { path, append };
-                }
+                let path = parse_redirect_target(tokens, i)?;
+                redirects.push(Redirection {
+                    fd,
+                    target: RedirectTarget::File { path, append },
+                });
             }
             // &> / &>>  (stdout, stderr 両方)
             Token::RedirectBoth | Token::RedirectBothAppend => {
-                let append = matches!(must_get(tokens, i)?, Token::RedirectBothAppend);
+                let append = matches!(token, Token::RedirectBothAppend);
                 *i += 1;
-                if matches!(must_get(tokens, i)?, Token::Word(_, _)) {
-                    let path = parse_word_node(tokens, i)?;
-                    stdout = Redirection::File {
-                        path: path.clone(),
-                        append,
-                    };
-                    stderr = Redirection::File { path, append };
-                }
+                let path = parse_redirect_target(tokens, i)?;
+                redirects.push(Redirection {
+                    fd: 1,
+                    target: RedirectTarget::File { path, append },
+                });
+                redirects.push(Redirection {
+                    fd: 2,
+                    target: RedirectTarget::Dup(1),
+                });
             }
-            // 2> / 2>>  (stderr)
-            Token::RedirectErr | Token::RedirectErrAppend => {
-                let append = matches!(must_get(tokens, i)?, Token::RedirectErrAppend);
+            // [n]<
+            Token::RedirectIn(fd) => {
                 *i += 1;
-                if matches!(must_get(tokens, i)?, Token::Word(_, _)) {
-                    let path = parse_word_node(tokens, i)?;
-                    stderr = Redirection::File { path, append };
-                }
+                let path = parse_redirect_target(tokens, i)?;
+                redirects.push(Redirection {
+                    fd,
+                    target: RedirectTarget::Input(path),
+                });
+            }
+            // n>&m / n<&m / n>&-
+            Token::DupOut(fd, target) | Token::DupIn(fd, target) => {
+                *i += 1;
+                let target = match target {
+                    Some(src) => RedirectTarget::Dup(src),
+                    None => RedirectTarget::Close,
+                };
+                redirects.push(Redirection { fd, target });
+            }
+            // n>&word
+            Token::BadDupOut(_) => return Err(unexpected(tokens, *i)),
+            // <<<
+            Token::HereString => {
+                *i += 1;
+                let word = parse_redirect_target(tokens, i)?;
+                redirects.push(Redirection {
+                    fd: 0,
+                    target: RedirectTarget::HereString(word),
+                });
+            }
+            // <<EOF
+            Token::HereDoc(doc) => {
+                *i += 1;
+                let body = if doc.strip_tabs {
+                    doc.body
+                        .split_inclusive('\n')
+                        .map(|line| line.trim_start_matches('\t'))
+                        .collect()
+                } else {
+                    doc.body.clone()
+                };
+                let node = if doc.expand {
+                    parse_word_node(&tokenize_heredoc(&body), &mut 0)?
+                } else {
+                    WordNode {
+                        segments: vec![Segment::SingleQuoted(body)],
+                    }
+                };
+                redirects.push(Redirection {
+                    fd: 0,
+                    target: RedirectTarget::HereDoc(node),
+                });
             }
 
             // パイプ境界や論理境界
             Token::Pipe => {
-                stdout = Redirection::Pipe;
+                pipe = PipeTo::Stdout;
                 break;
             }
             Token::PipeErr => {
-                *i += 1;
-                stderr = Redirection::Pipe;
+                pipe = PipeTo::Stderr;
+                break;
             }
             Token::PipeBoth => {
-                *i += 1;
-                stdout = Redirection::Pipe;
-                stderr = Redirection::Pipe;
+                pipe = PipeTo::Both;
+                break;
+            }
+            Token::And | Token::Or | Token::Semicolon | Token::Background | Token::Newline => {
+                break;
             }
-            Token::And | Token::Or => break,
 
+            // グループ内では単独の } で終わる
+            Token::Word(_, _) if in_group && is_close_brace(tokens, *i) => break,
+            // グループの後ろに引数は置けない
+            Token::Word(_, _) if group.is_some() => return Err(unexpected(tokens, *i)),
             // 引数
             Token::Word(_, _) => {
                 let arg = parse_word_node(tokens, i)?;
@@ -222,14 +714,25 @@ fn parse_command(tokens: &[Token], i: &mut usize) -> Result<CommandExpr> {
     }
 
     Ok(CommandExpr {
+        assignments,
         cmd_name,
         args,
-        stdout,
-        stderr,
+        pipe,
+        redirects,
+        group,
     })
 }
 
-fn must_get<'a>(tokens: &'a [Token], i: &'a mut usize) -> Result<&'a Token> {
+/// リダイレクト演算子の直後の単語（ファイル名など）
+fn parse_redirect_target(tokens: &Tokens, i: &mut usize) -> Result<WordNode> {
+    let op_at = tokens.span(*i - 1).start;
+    match skip_delimiter_get(tokens, i) {
+        Some(Token::Word(_, _)) => parse_word_node(tokens, i),
+        _ => Err(Error::MissingRedirectTarget { at: op_at }),
+    }
+}
+
+fn must_get<'a>(tokens: &'a Tokens, i: &'a mut usize) -> Result<&'a Token> {
     match tokens.get(*i) {
         Some(x) => match x {
             Token::Delimiter => {
@@ -238,7 +741,33 @@ fn must_get<'a>(tokens: &'a [Token], i: &'a mut usize) -> Result<&'a Token> {
             }
             x => Ok(x),
         },
-        None => Err(Error::StructureCollaps),
+        None => Err(unexpected(tokens, *i)),
+    }
+}
+
+/// i 番目のトークン（なければ入力の終わり）が予期しないものだった。
+/// 入力の終わりなら続きの行で解釈できるかもしれないので Incomplete にする
+fn unexpected(tokens: &Tokens, i: usize) -> Error {
+    let at = tokens.span(i).start;
+    match tokens.get(i) {
+        Some(t) => {
+            let token = match t {
+                Token::Newline => "newline".to_string(),
+                t => tokens_to_string(std::slice::from_ref(t)),
+            };
+            Error::UnexpectedToken {
+                token: Some(token),
+                at,
+            }
+        }
+        None => Error::Incomplete(Box::new(Error::UnexpectedToken { token: None, at })),
+    }
+}
+
+/// `&&` `||` `|` の直後の改行は読み飛ばす
+fn skip_newlines(tokens: &[Token], i: &mut usize) {
+    while matches!(tokens.get(*i), Some(Token::Newline | Token::Delimiter)) {
+        *i += 1;
     }
 }
 
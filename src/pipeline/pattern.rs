@@ -0,0 +1,263 @@
+// ワイルドカードパターン（`*` `?` `[...]`、拡張の `(a|b)` `!(pat)`）の照合
+
+/// pattern が text 全体に一致するか
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    if p.contains(&'(') {
+        match_ext(&p, &t)
+    } else {
+        match_from(&p, &t)
+    }
+}
+
+fn match_from(p: &[char], t: &[char]) -> bool {
+    // `*` の位置を覚えておき、失敗したらそこから 1 文字ずらして再試行する
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() {
+            match p[pi] {
+                '*' => {
+                    star = Some((pi, ti));
+                    pi += 1;
+                    continue;
+                }
+                '?' => {
+                    pi += 1;
+                    ti += 1;
+                    continue;
+                }
+                '[' => match match_class(p, pi, t[ti]) {
+                    Some((true, next)) => {
+                        pi = next;
+                        ti += 1;
+                        continue;
+                    }
+                    Some((false, _)) => {}
+                    // 閉じていない [ はただの文字
+                    None if t[ti] == '[' => {
+                        pi += 1;
+                        ti += 1;
+                        continue;
+                    }
+                    None => {}
+                },
+                '\\' if pi + 1 < p.len() && p[pi + 1] == t[ti] => {
+                    pi += 2;
+                    ti += 1;
+                    continue;
+                }
+                '\\' if pi + 1 < p.len() => {}
+                c if c == t[ti] => {
+                    pi += 1;
+                    ti += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match star {
+            Some((sp, st)) => {
+                pi = sp + 1;
+                ti = st + 1;
+                star = Some((sp, st + 1));
+            }
+            None => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// グループを含むパターン用。グループの長さが決まらないので素直に再帰で試す
+fn match_ext(p: &[char], t: &[char]) -> bool {
+    let Some(&c) = p.first() else {
+        return t.is_empty();
+    };
+    match c {
+        '*' => (0..=t.len()).any(|k| match_ext(&p[1..], &t[k..])),
+        '?' => !t.is_empty() && match_ext(&p[1..], &t[1..]),
+        '[' => {
+            let Some(&tc) = t.first() else {
+                return false;
+            };
+            match match_class(p, 0, tc) {
+                Some((matched, next)) => matched && match_ext(&p[next..], &t[1..]),
+                None => tc == '[' && match_ext(&p[1..], &t[1..]),
+            }
+        }
+        '\\' if p.len() > 1 => t.first() == Some(&p[1]) && match_ext(&p[2..], &t[1..]),
+        '(' | '!' if let Some((negate, alts, rest)) = group(p) => (0..=t.len()).any(|k| {
+            let hit = alts.iter().any(|a| match_ext(a, &t[..k]));
+            hit != negate && match_ext(rest, &t[k..])
+        }),
+        c => t.first() == Some(&c) && match_ext(&p[1..], &t[1..]),
+    }
+}
+
+/// 先頭の `(a|b)` / `!(a|b)` を (否定か, 選択肢, 残り) に分ける。閉じていなければ None
+type Group<'a> = (bool, Vec<&'a [char]>, &'a [char]);
+fn group(p: &[char]) -> Option<Group<'_>> {
+    let negate = p[0] == '!';
+    let start = if negate { 2 } else { 1 };
+    if negate && p.get(1) != Some(&'(') {
+        return None;
+    }
+    let mut alts = Vec::new();
+    let mut depth = 0;
+    let mut from = start;
+    let mut i = start;
+    while i < p.len() {
+        match p[i] {
+            '\\' => i += 1,
+            '(' => depth += 1,
+            '|' if depth == 0 => {
+                alts.push(&p[from..i]);
+                from = i + 1;
+            }
+            ')' if depth == 0 => {
+                alts.push(&p[from..i]);
+                return Some((negate, alts, &p[i + 1..]));
+            }
+            ')' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// `[...]` を c と照合する。戻り値は (一致したか, 閉じ括弧の次の位置)。
+/// 閉じていない `[` は None（呼び出し側で通常の文字として扱う）
+fn match_class(p: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(p.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < p.len() {
+        if p[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        let lo = p[i];
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            let hi = p[i + 2];
+            if lo <= c && c <= hi {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if lo == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+    None
+}
+
+/// エスケープされていない `*` `?` `[` や閉じたグループを含むか
+pub fn has_wildcard(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            '(' if chars.clone().any(|c| c == ')') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// 特殊文字を `\` でエスケープし、そのままの文字列として照合されるようにする
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '(' | ')' | '|' | '!' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// escape の逆。ワイルドカードを含まない部分を実際の文字列に戻す
+pub fn unescape(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "main.rc"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(matches("?at", "cat"));
+        assert!(!matches("?at", "at"));
+        assert!(matches("*", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc]x", "bx"));
+        assert!(matches("[a-c]x", "cx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(matches("[^a-c]x", "dx"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        // 閉じていない [ はただの文字
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn groups() {
+        assert!(matches("*.(rs|toml)", "Cargo.toml"));
+        assert!(matches("*.(rs|toml)", "main.rs"));
+        assert!(!matches("*.(rs|toml)", "README.md"));
+        assert!(matches("!(*.rs)", "README.md"));
+        assert!(!matches("!(*.rs)", "main.rs"));
+        assert!(matches("a((b|c)|d)e", "ace"));
+        // 閉じていないグループはただの文字
+        assert!(matches("(ab", "(ab"));
+    }
+
+    #[test]
+    fn escaped_text_is_literal() {
+        let text = "a*b?[c](d|e)!\\";
+        assert!(matches(&escape(text), text));
+        assert!(!matches(&escape("a*"), "abc"));
+        assert!(!has_wildcard(&escape(text)));
+        assert_eq!(unescape(&escape(text)), text);
+    }
+
+    #[test]
+    fn wildcard_detection() {
+        assert!(has_wildcard("*.rs"));
+        assert!(has_wildcard("a?"));
+        assert!(has_wildcard("[ab]"));
+        assert!(has_wildcard("(a|b)"));
+        assert!(!has_wildcard("(ab"));
+        assert!(!has_wildcard("plain"));
+        assert!(!has_wildcard("\\*"));
+    }
+}
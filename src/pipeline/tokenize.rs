@@ -1,20 +1,29 @@
-use std::mem;
+use std::{mem, ops::Deref};
+
+use super::pattern;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Word(String, QuoteKind),
-    And,                // &&
-    Or,                 // ||
-    RedirectOut,        // >
-    RedirectBoth,       // &>
-    RedirectErr,        // 2>
-    RedirectAppend,     // >>
-    RedirectBothAppend, // &>>
-    RedirectErrAppend,  // 2>>
-    Pipe,               // |
-    PipeErr,            // 2|
-    PipeBoth,           // &|
-    Delimiter,          // 区切り文字(token間のspaceを明示)
+    And,                      // &&
+    Or,                       // ||
+    RedirectOut(i32),         // [n]>
+    RedirectAppend(i32),      // [n]>>
+    RedirectIn(i32),          // [n]<
+    DupOut(i32, Option<i32>), // [n]>&m / [n]>&-（None は close）
+    DupIn(i32, Option<i32>),  // [n]<&m / [n]<&-
+    BadDupOut(i32),           // n>&word（複製先が番号でない。構文エラーにする）
+    RedirectBoth,             // &>
+    RedirectBothAppend,       // &>>
+    HereDoc(HereDoc),         // <<EOF（本文は次の改行の後から読み取る）
+    HereString,               // <<<
+    Pipe,                     // |
+    PipeErr,                  // 2|
+    PipeBoth,                 // &|
+    Semicolon,                // ;
+    Background,               // &
+    Newline,                  // 改行
+    Delimiter,                // 区切り文字(token間のspaceを明示)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,58 +32,178 @@ pub enum QuoteKind {
     Single,
     Double,
     Variable,
+    QuotedVariable, // "$var"
     Tilde,
+    Command,       // (cmd) / $(cmd)
+    QuotedCommand, // "$(cmd)"
+    Arith,         // $(( expr ))（中身の式だけを持つ）
+    ProcSubst,     // <(cmd) / >(cmd)
+}
+
+/// 入力中の位置（バイト単位の半開区間）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 入力中の位置 at を (行, 桁) にする。どちらも 1 始まりで、桁は文字数で数える
+pub fn line_col(input: &str, at: usize) -> (usize, usize) {
+    let mut at = at.min(input.len());
+    while !input.is_char_boundary(at) {
+        at -= 1;
+    }
+    let before = &input[..at];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |p| p + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// tokenize の結果。トークン列として扱え、各トークンの入力中の位置も引ける
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    list: Vec<Token>,
+    spans: Vec<Span>,                          // list と同じ長さ
+    len: usize,                                // 入力の長さ（入力の終わりの位置）
+    pub unclosed_quote: Option<(char, usize)>, // 閉じていないクォートとその位置
+    pub line_continued: bool,                  // 行継続の `\` で終わっている
+}
+
+impl Deref for Tokens {
+    type Target = [Token];
+
+    fn deref(&self) -> &[Token] {
+        &self.list
+    }
+}
+
+impl Tokens {
+    /// i 番目のトークンの位置。範囲外なら入力の終わり
+    pub fn span(&self, i: usize) -> Span {
+        self.spans.get(i).copied().unwrap_or(Span {
+            start: self.len,
+            end: self.len,
+        })
+    }
+
+    /// i 番目のトークンを別のトークン列で置き換える（alias / abbr 展開）。
+    /// 置き換えたトークンは元のトークンの位置を引き継ぐ
+    pub fn splice(&mut self, i: usize, repl: Tokens) {
+        let span = self.span(i);
+        let n = repl.list.len();
+        self.list.splice(i..=i, repl.list);
+        self.spans.splice(i..=i, vec![span; n]);
+    }
+}
+
+/// 入力を 1 文字ずつ読む。Peekable<Chars> と同じように使え、読んだ位置（バイト）も分かる
+#[derive(Clone)]
+pub(super) struct Reader<'a> {
+    rest: &'a str,
+    len: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            rest: input,
+            len: input.len(),
+        }
+    }
+
+    /// 次に読む文字の位置
+    fn pos(&self) -> usize {
+        self.len - self.rest.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn next_if(&mut self, f: impl FnOnce(&char) -> bool) -> Option<char> {
+        let c = self.peek()?;
+        if f(&c) { self.next() } else { None }
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|c| c == expected)
+    }
+}
+
+impl Iterator for Reader<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.rest = &self.rest[c.len_utf8()..];
+        Some(c)
+    }
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-    use std::iter::Peekable;
-    use std::str::Chars;
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct HereDoc {
+    pub delimiter: String,
+    pub expand: bool,     // 区切り語がクォートされていなければ本文を展開する
+    pub strip_tabs: bool, // <<- の場合は各行先頭のタブを除く
+    pub body: String,
+    pub terminated: bool, // 終端行まで読み終えたか
+}
 
-    fn peek2(it: &Peekable<Chars<'_>>) -> (Option<char>, Option<char>) {
+pub fn tokenize(input: &str) -> Tokens {
+    fn peek2(it: &Reader<'_>) -> (Option<char>, Option<char>) {
         let mut it2 = it.clone();
         (it2.next(), it2.next())
     }
 
-    fn match_operator(ch: char, it: &Peekable<Chars<'_>>) -> Option<(Token, usize)> {
+    /// 戻り値は (トークン, 文字数, fd 指定を消費したか)
+    fn match_operator(ch: char, it: &Reader<'_>, fd: Option<i32>) -> Option<(Token, usize, bool)> {
         let (p1, p2) = peek2(it);
+        let out = fd.unwrap_or(1);
+        let input = fd.unwrap_or(0);
         match (ch, p1, p2) {
             // 3文字
-            ('&', Some('>'), Some('>')) => Some((Token::RedirectBothAppend, 3)),
-            ('2', Some('>'), Some('>')) => Some((Token::RedirectErrAppend, 3)),
+            ('&', Some('>'), Some('>')) => Some((Token::RedirectBothAppend, 3, false)),
+            ('<', Some('<'), Some('<')) => Some((Token::HereString, 3, false)),
             // 2文字
-            ('&', Some('&'), _) => Some((Token::And, 2)),
-            ('|', Some('|'), _) => Some((Token::Or, 2)),
-            ('>', Some('>'), _) => Some((Token::RedirectAppend, 2)),
-            ('&', Some('>'), _) => Some((Token::RedirectBoth, 2)),
-            ('2', Some('>'), _) => Some((Token::RedirectErr, 2)),
-            ('&', Some('|'), _) => Some((Token::PipeBoth, 2)),
-            ('2', Some('|'), _) => Some((Token::PipeErr, 2)),
+            ('&', Some('&'), _) => Some((Token::And, 2, false)),
+            ('|', Some('|'), _) => Some((Token::Or, 2, false)),
+            ('>', Some('>'), _) => Some((Token::RedirectAppend(out), 2, true)),
+            ('>', Some('&'), _) => Some((Token::DupOut(out, None), 2, true)),
+            ('<', Some('&'), _) => Some((Token::DupIn(input, None), 2, true)),
+            ('&', Some('>'), _) => Some((Token::RedirectBoth, 2, false)),
+            ('&', Some('|'), _) => Some((Token::PipeBoth, 2, false)),
+            ('<', Some('<'), _) => Some((Token::HereDoc(HereDoc::default()), 2, false)),
             // 1文字
-            ('|', _, _) => Some((Token::Pipe, 1)),
-            ('>', _, _) => Some((Token::RedirectOut, 1)),
+            ('|', _, _) if fd == Some(2) => Some((Token::PipeErr, 1, true)),
+            ('|', _, _) => Some((Token::Pipe, 1, false)),
+            ('>', _, _) => Some((Token::RedirectOut(out), 1, true)),
+            ('<', _, _) => Some((Token::RedirectIn(input), 1, true)),
+            ('&', _, _) => Some((Token::Background, 1, false)),
             _ => None,
         }
     }
 
     let mut tokens = Vec::new();
+    let mut spans = Vec::new();
     let mut current = String::new();
-    let mut chars = input.chars().peekable();
-    let mut in_variable = false;
+    let mut chars = Reader::new(input);
     let mut in_single = false;
     let mut in_double = false;
+    let mut double_start = 0; // `"` を開いた時点の tokens.len()
+    let mut quote_start = 0; // 開いているクォートの位置
+    let mut pending_heredocs: Vec<usize> = Vec::new(); // 本文待ちの HereDoc の位置
+    let mut start = 0; // 今読んでいる文字の位置
+    let mut word_start = 0; // current を溜め始めた位置
+    let mut line_continued = false;
 
-    while let Some(ch) = chars.next() {
-        if in_variable {
-            match ch {
-                'a'..'z' | 'A'..'Z' | '_' => {
-                    current.push(ch);
-                    continue;
-                }
-                _ => {
-                    in_variable = false;
-                    tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Variable));
-                }
-            }
+    loop {
+        fill_spans(&tokens, &mut spans, word_start, start, chars.pos());
+        let Some(ch) = chars.next() else {
+            break;
+        };
+        start = chars.pos() - ch.len_utf8();
+        if current.is_empty() {
+            word_start = start;
         }
 
         if in_single {
@@ -92,7 +221,10 @@ pub fn tokenize(input: &str) -> Vec<Token> {
             match ch {
                 '"' => {
                     in_double = false;
-                    tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                    // 空の "" は空文字列の引数として残す
+                    if !current.is_empty() || tokens.len() == double_start {
+                        tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                    }
                 }
                 '\\' => {
                     // 簡易: 次の1文字をそのまま取り込む（\" や \\ を保持）
@@ -102,10 +234,28 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                         current.push('\\');
                     }
                 }
-                '$' => {
-                    in_variable = true;
-                    tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                '$' if chars.peek() == Some('(') => {
+                    chars.next();
+                    match read_subst(&mut chars) {
+                        (body, true) => {
+                            if !current.is_empty() {
+                                tokens
+                                    .push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                            }
+                            tokens.push(subst_token(body, QuoteKind::QuotedCommand));
+                        }
+                        (body, false) => current.push_str(&format!("$({body}")),
+                    }
                 }
+                '$' => match read_variable(&mut chars) {
+                    Some(name) => {
+                        if !current.is_empty() {
+                            tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                        }
+                        tokens.push(Token::Word(name, QuoteKind::QuotedVariable));
+                    }
+                    None => current.push('$'),
+                },
                 _ => current.push(ch),
             }
             continue;
@@ -114,53 +264,160 @@ pub fn tokenize(input: &str) -> Vec<Token> {
         // ── クォート外 ────────────────────────────────
         match ch {
             // 区切り（空白）
-            ' ' | '\t' | '\n' | '\r' => {
+            ' ' | '\t' | '\r' => {
                 if !current.is_empty() {
                     tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
                 }
                 tokens.push(Token::Delimiter);
             }
+            // コマンドの区切り
+            ';' | '\n' => {
+                if !current.is_empty() {
+                    tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
+                }
+                if ch == ';' {
+                    tokens.push(Token::Semicolon);
+                    continue;
+                }
+                tokens.push(Token::Newline);
+                // 改行の後ろはヒアドキュメントの本文
+                for idx in pending_heredocs.drain(..) {
+                    if let Token::HereDoc(doc) = &mut tokens[idx] {
+                        read_heredoc_body(&mut chars, doc);
+                    }
+                }
+            }
+            // コメント（単語の先頭の # から行末まで）
+            '#' if current.is_empty() && !matches!(tokens.last(), Some(Token::Word(..))) => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
             // クォート開始
             '\'' => {
                 if !current.is_empty() {
                     tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
                 }
                 in_single = true;
+                quote_start = start;
             }
             '"' => {
                 if !current.is_empty() {
                     tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
                 }
                 in_double = true;
+                double_start = tokens.len();
+                quote_start = start;
             }
-            // バックスラッシュ（簡易）
+            // バックスラッシュ。次の 1 文字は '...' と同じくそのままの文字（グロブやブレースにならない）。
+            // 行末の \ は行継続
             '\\' => {
-                if let Some(nc) = chars.next() {
-                    current.push(nc);
+                if chars.next_if_eq(&'\n').is_some() {
+                    continue;
+                }
+                match chars.next() {
+                    Some(nc) => {
+                        if !current.is_empty() {
+                            tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
+                        }
+                        tokens.push(Token::Word(nc.to_string(), QuoteKind::Single));
+                    }
+                    None => line_continued = true,
+                }
+            }
+            // グロブ中の `!(..)` `*.(a|b)` `*(.)` は語の一部として残す
+            '(' if current.ends_with('!') || pattern::has_wildcard(&current) => {
+                match read_subst(&mut chars) {
+                    (body, true) => current.push_str(&format!("({body})")),
+                    (body, false) => current.push_str(&format!("({body}")),
+                }
+            }
+            // コマンド置換 (cmd) / $(cmd)
+            '(' | '$' if ch == '(' || chars.peek() == Some('(') => {
+                let prefix = if ch == '$' {
+                    chars.next();
+                    "$("
                 } else {
-                    current.push('\\');
+                    "("
+                };
+                match read_subst(&mut chars) {
+                    (body, true) => {
+                        if !current.is_empty() {
+                            tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
+                        }
+                        tokens.push(if prefix == "$(" {
+                            subst_token(body, QuoteKind::Command)
+                        } else {
+                            Token::Word(format!("({body})"), QuoteKind::Command)
+                        });
+                    }
+                    // 閉じていない場合はリテラルとして扱う
+                    (body, false) => current.push_str(&format!("{prefix}{body}")),
                 }
             }
-            '$' => {
+            '$' => match read_variable(&mut chars) {
+                Some(name) => {
+                    if !current.is_empty() {
+                        tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
+                    }
+                    tokens.push(Token::Word(name, QuoteKind::Variable));
+                }
+                None => current.push('$'),
+            },
+            // プロセス置換 <(cmd) / >(cmd)（語頭のみ。`2>(..)` などはリダイレクト）
+            '<' | '>' if current.is_empty() && chars.peek() == Some('(') => {
+                chars.next();
+                match read_subst(&mut chars) {
+                    (body, true) => {
+                        tokens.push(Token::Word(format!("{ch}({body})"), QuoteKind::ProcSubst))
+                    }
+                    (body, false) => current.push_str(&format!("{ch}({body}")),
+                }
+            }
+            // 語頭か `=` の直後の ~ はチルダ展開（`~` `~user` `~+` `~-` `~N`）。それ以外はただの文字
+            '~' if current.ends_with('=')
+                || (current.is_empty() && !matches!(tokens.last(), Some(Token::Word(..)))) =>
+            {
                 if !current.is_empty() {
                     tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
                 }
-                in_variable = true;
-            }
-            '~' => {
-                if current.is_empty() {
-                    tokens.push(Token::Word("~".to_string(), QuoteKind::Tilde));
+                let mut word = String::from("~");
+                while let Some(c) = chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '+'))
+                {
+                    word.push(c);
                 }
+                tokens.push(Token::Word(word, QuoteKind::Tilde));
             }
             // 演算子（最長一致）
             _ => {
-                if let Some((tok, len)) = match_operator(ch, &chars) {
-                    if !current.is_empty() {
+                let fd = fd_prefix(&current, &tokens);
+                if let Some((tok, len, used_fd)) = match_operator(ch, &chars, fd) {
+                    if used_fd && fd.is_some() {
+                        current.clear();
+                    } else if !current.is_empty() {
                         tokens.push(Token::Word(mem::take(&mut current), QuoteKind::None));
                     }
                     for _ in 1..len {
                         chars.next();
                     }
+                    let tok = match tok {
+                        Token::DupOut(n, _) => match read_dup_target(&mut chars) {
+                            Some(target) => Token::DupOut(n, target),
+                            // `>&file` は `&>file` と同じ。fd を明示した `2>&file` は受け付けない
+                            None if fd.is_none() => Token::RedirectBoth,
+                            None => Token::BadDupOut(n),
+                        },
+                        Token::DupIn(n, _) => match read_dup_target(&mut chars) {
+                            Some(target) => Token::DupIn(n, target),
+                            None => Token::RedirectIn(n),
+                        },
+                        tok => tok,
+                    };
+                    if let Token::HereDoc(mut doc) = tok {
+                        read_heredoc_delimiter(&mut chars, &mut doc);
+                        pending_heredocs.push(tokens.len());
+                        tokens.push(Token::HereDoc(doc));
+                        continue;
+                    }
                     tokens.push(tok);
                 } else {
                     current.push(ch);
@@ -170,14 +427,287 @@ pub fn tokenize(input: &str) -> Vec<Token> {
     }
 
     if !current.is_empty() {
-        if in_variable {
-            tokens.push(Token::Word(current, QuoteKind::Variable));
+        tokens.push(Token::Word(current, QuoteKind::None));
+    }
+    fill_spans(&tokens, &mut spans, word_start, input.len(), input.len());
+
+    let unclosed_quote = match (in_single, in_double) {
+        (true, _) => Some(('\'', quote_start)),
+        (_, true) => Some(('"', quote_start)),
+        _ => None,
+    };
+    Tokens {
+        list: tokens,
+        spans,
+        len: input.len(),
+        unclosed_quote,
+        line_continued,
+    }
+}
+
+/// 前回から増えたトークンに位置を付ける。
+/// 1 度に複数増えたときは、最後以外はこの文字の手前で切り出された単語
+fn fill_spans(
+    tokens: &[Token],
+    spans: &mut Vec<Span>,
+    word_start: usize,
+    start: usize,
+    end: usize,
+) {
+    let single = spans.len() + 1 == tokens.len();
+    while spans.len() < tokens.len() {
+        let last = spans.len() + 1 == tokens.len();
+        let span = match &tokens[spans.len()] {
+            _ if !last => Span {
+                start: word_start,
+                end: start,
+            },
+            Token::Word(..) if single => Span {
+                start: word_start.min(start),
+                end,
+            },
+            _ => Span { start, end },
+        };
+        spans.push(span);
+    }
+}
+
+/// 単語の先頭から数字だけが続いていれば、それをリダイレクトの fd とみなす（`2>` や `3<` など）
+fn fd_prefix(current: &str, tokens: &[Token]) -> Option<i32> {
+    if current.is_empty()
+        || !current.chars().all(|c| c.is_ascii_digit())
+        || matches!(tokens.last(), Some(Token::Word(..)))
+    {
+        return None;
+    }
+    current.parse().ok()
+}
+
+/// `>&` / `<&` の後ろの複製元 fd を読み取る。`-` は close（Some(None)）。
+/// 数字でも `-` でもなければ None を返す。
+fn read_dup_target(chars: &mut Reader<'_>) -> Option<Option<i32>> {
+    if chars.next_if_eq(&'-').is_some() {
+        return Some(None);
+    }
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    if digits.is_empty() {
+        None
+    } else {
+        Some(digits.parse().ok())
+    }
+}
+
+/// `<<` の直後から区切り語を読み取る。クォートされていれば本文を展開しない。
+fn read_heredoc_delimiter(chars: &mut Reader<'_>, doc: &mut HereDoc) {
+    doc.strip_tabs = chars.next_if_eq(&'-').is_some();
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+    doc.expand = true;
+    let mut quote: Option<char> = None;
+    while let Some(ch) = chars.peek() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => doc.delimiter.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                doc.expand = false;
+            }
+            (None, '\\') => {
+                doc.expand = false;
+                chars.next();
+                if let Some(nc) = chars.next() {
+                    doc.delimiter.push(nc);
+                }
+                continue;
+            }
+            (None, ' ' | '\t' | '\n' | ';' | '|' | '&' | '<' | '>' | '(' | ')') => break,
+            (None, c) => doc.delimiter.push(c),
+        }
+        chars.next();
+    }
+}
+
+/// 改行の直後から終端行までを本文として読み取る
+fn read_heredoc_body(chars: &mut Reader<'_>, doc: &mut HereDoc) {
+    loop {
+        let mut line = String::new();
+        let mut has_newline = false;
+        for ch in chars.by_ref() {
+            if ch == '\n' {
+                has_newline = true;
+                break;
+            }
+            line.push(ch);
+        }
+        if line.is_empty() && !has_newline {
+            return;
+        }
+        let cmp = if doc.strip_tabs {
+            line.trim_start_matches('\t')
         } else {
-            tokens.push(Token::Word(current, QuoteKind::None));
+            &line
+        };
+        if cmp == doc.delimiter {
+            doc.terminated = true;
+            return;
         }
+        doc.body.push_str(&line);
+        if !has_newline {
+            return;
+        }
+        doc.body.push('\n');
     }
+}
 
+/// ヒアドキュメントの本文（展開あり）を Double / Variable / QuotedCommand へ分解する
+pub fn tokenize_heredoc(body: &str) -> Tokens {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = Reader::new(body);
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if matches!(chars.peek(), Some('$' | '\\' | '`')) => {
+                current.push(chars.next().unwrap());
+            }
+            '$' if chars.peek() == Some('(') => {
+                chars.next();
+                match read_subst(&mut chars) {
+                    (inner, true) => {
+                        if !current.is_empty() {
+                            tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                        }
+                        tokens.push(subst_token(inner, QuoteKind::QuotedCommand));
+                    }
+                    (inner, false) => current.push_str(&format!("$({inner}")),
+                }
+            }
+            '$' => match read_variable(&mut chars) {
+                Some(name) => {
+                    if !current.is_empty() {
+                        tokens.push(Token::Word(mem::take(&mut current), QuoteKind::Double));
+                    }
+                    tokens.push(Token::Word(name, QuoteKind::QuotedVariable));
+                }
+                None => current.push('$'),
+            },
+            _ => current.push(ch),
+        }
+    }
+    if !current.is_empty() || tokens.is_empty() {
+        tokens.push(Token::Word(current, QuoteKind::Double));
+    }
+    // 本文中の位置は使わない
+    Tokens {
+        list: tokens,
+        ..Tokens::default()
+    }
+}
+
+/// 本文待ちのヒアドキュメントが残っているか（続きの行の入力が必要か）
+pub fn has_pending_heredoc(tokens: &[Token]) -> bool {
     tokens
+        .iter()
+        .any(|t| matches!(t, Token::HereDoc(doc) if !doc.terminated))
+}
+
+/// 変数名の 1 文字目（英字か _）
+fn is_name_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || ch == '_'
+}
+
+/// 変数名の 2 文字目以降（英数字か _）
+fn is_name_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+/// `$` の直後から変数名を読み取る。
+/// `${...}` は波括弧ごと返す（中身の解釈は parse 側）。変数名が続かなければ何も消費せず None
+fn read_variable(chars: &mut Reader<'_>) -> Option<String> {
+    match chars.peek()? {
+        '{' => {
+            let mut look = chars.clone();
+            let mut name = String::new();
+            let mut depth = 0;
+            for c in look.by_ref() {
+                name.push(c);
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            *chars = look;
+                            return Some(name);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            None // 閉じていなければリテラル
+        }
+        // $? $$ と位置パラメータ $0..$9
+        c @ ('?' | '$' | '0'..='9') => {
+            chars.next();
+            Some(c.to_string())
+        }
+        c if is_name_start(c) => {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| is_name_char(*c)) {
+                name.push(c);
+            }
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// `$(` に続く中身からトークンを作る。最初の `(` が末尾の `)` で閉じる `$((expr))` なら算術式。
+/// `$((a)|(b))` のように途中で閉じるものはサブシェルのコマンド置換
+fn subst_token(body: String, kind: QuoteKind) -> Token {
+    if let Some(rest) = body.strip_prefix('(') {
+        let mut chars = Reader::new(rest);
+        if let (expr, true) = read_subst(&mut chars)
+            && chars.peek().is_none()
+        {
+            return Token::Word(expr, QuoteKind::Arith);
+        }
+    }
+    Token::Word(format!("$({body})"), kind)
+}
+
+/// `(` の直後から対応する `)` までを読み取る（ネストとクォートを考慮）
+/// 戻り値は (中身, 閉じ括弧があったか)。閉じていない場合は末尾までを返す。
+fn read_subst(chars: &mut Reader<'_>) -> (String, bool) {
+    let mut body = String::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                body.push(ch);
+                if let Some(nc) = chars.next() {
+                    body.push(nc);
+                }
+                continue;
+            }
+            (Some(_), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                if depth == 0 {
+                    return (body, true);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        body.push(ch);
+    }
+    (body, false)
 }
 
 pub fn tokens_to_string(tokens: &[Token]) -> String {
@@ -205,28 +735,120 @@ pub fn tokens_to_string(tokens: &[Token]) -> String {
         format!("\"{}\"", escaped)
     }
 
+    fn fd_str(fd: i32, default: i32) -> String {
+        if fd == default {
+            String::new()
+        } else {
+            fd.to_string()
+        }
+    }
+
+    fn target_str(target: Option<i32>) -> String {
+        target.map_or("-".to_string(), |t| t.to_string())
+    }
+
     let mut parts = Vec::with_capacity(tokens.len());
+    let mut pending_heredocs: Vec<&HereDoc> = Vec::new();
+    let mut after_heredoc = false; // 終端行の後ろに続くトークンがあれば改行を挟む
     for t in tokens {
+        if mem::take(&mut after_heredoc) {
+            parts.push("\n".to_string());
+        }
         let s = match t {
             Token::Word(w, QuoteKind::None) => w.clone(),
             Token::Word(w, QuoteKind::Single) => quote_single(w),
             Token::Word(w, QuoteKind::Double) => quote_double(w),
             Token::Word(w, QuoteKind::Variable) => "$".to_string() + w,
+            Token::Word(w, QuoteKind::QuotedVariable) => format!("\"${w}\""),
             Token::Word(w, QuoteKind::Tilde) => w.to_string(),
+            Token::Word(w, QuoteKind::Command) => w.clone(),
+            Token::Word(w, QuoteKind::QuotedCommand) => format!("\"{w}\""),
+            Token::Word(w, QuoteKind::Arith) => format!("$(({w}))"),
+            Token::Word(w, QuoteKind::ProcSubst) => w.clone(),
             Token::And => "&&".to_string(),
             Token::Or => "||".to_string(),
-            Token::RedirectOut => ">".to_string(),
+            Token::RedirectOut(fd) => fd_str(*fd, 1) + ">",
+            Token::RedirectAppend(fd) => fd_str(*fd, 1) + ">>",
+            Token::RedirectIn(fd) => fd_str(*fd, 0) + "<",
+            Token::DupOut(fd, target) => fd_str(*fd, 1) + ">&" + &target_str(*target),
+            Token::DupIn(fd, target) => fd_str(*fd, 0) + "<&" + &target_str(*target),
+            Token::BadDupOut(fd) => format!("{fd}>&"),
             Token::RedirectBoth => "&>".to_string(),
-            Token::RedirectErr => "2>".to_string(),
-            Token::RedirectAppend => ">>".to_string(),
             Token::RedirectBothAppend => "&>>".to_string(),
-            Token::RedirectErrAppend => "2>>".to_string(),
+            Token::HereDoc(doc) => {
+                pending_heredocs.push(doc);
+                let dash = if doc.strip_tabs { "-" } else { "" };
+                if doc.expand {
+                    format!("<<{dash}{}", doc.delimiter)
+                } else {
+                    format!("<<{dash}{}", quote_single(&doc.delimiter))
+                }
+            }
+            Token::HereString => "<<<".to_string(),
             Token::Pipe => "|".to_string(),
             Token::PipeErr => "2|".to_string(),
             Token::PipeBoth => "&|".to_string(),
+            Token::Semicolon => ";".to_string(),
+            Token::Background => "&".to_string(),
+            Token::Newline => {
+                let mut s = "\n".to_string();
+                for doc in pending_heredocs.drain(..) {
+                    if mem::take(&mut after_heredoc) {
+                        s.push('\n');
+                    }
+                    s.push_str(&doc.body);
+                    if doc.terminated {
+                        s.push_str(&doc.delimiter);
+                        after_heredoc = true;
+                    }
+                }
+                s
+            }
             Token::Delimiter => " ".to_string(),
         };
         parts.push(s);
     }
     parts.join("")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, kind: QuoteKind) -> Token {
+        Token::Word(text.to_string(), kind)
+    }
+
+    #[test]
+    fn arithmetic_only_when_the_first_paren_closes_at_the_end() {
+        assert_eq!(
+            tokenize("$((1 + 2))")[..],
+            [word("1 + 2", QuoteKind::Arith)]
+        );
+        assert_eq!(
+            tokenize("$(( (1 + 2) * 3 ))")[..],
+            [word(" (1 + 2) * 3 ", QuoteKind::Arith)]
+        );
+        assert_eq!(
+            tokenize("$((1)+(2))")[..],
+            [word("$((1)+(2))", QuoteKind::Command)]
+        );
+        assert_eq!(
+            tokenize("\"$((echo a)|(tr a b))\"")[..],
+            [word("$((echo a)|(tr a b))", QuoteKind::QuotedCommand)]
+        );
+    }
+
+    #[test]
+    fn dup_to_a_word_needs_no_explicit_fd() {
+        assert_eq!(
+            tokenize(">&out")[..],
+            [Token::RedirectBoth, word("out", QuoteKind::None)]
+        );
+        assert_eq!(
+            tokenize("2>&out")[..],
+            [Token::BadDupOut(2), word("out", QuoteKind::None)]
+        );
+        assert_eq!(tokenize("2>&1")[..], [Token::DupOut(2, Some(1))]);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, BuiltinResult, Streams};
 use crate::shell::Shell;
 
 pub struct AbbrCmd;
@@ -8,8 +8,8 @@ impl Builtin for AbbrCmd {
         "abbr"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        abbr(argv, shell)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(abbr(argv, shell))
     }
 }
 
@@ -0,0 +1,59 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::{Shell, jobs::JobState};
+
+pub struct BgCmd;
+
+impl Builtin for BgCmd {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(bg_with_args(shell, argv))
+    }
+}
+
+fn bg_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    let spec = match args {
+        [] => None,
+        [spec] => Some(spec.as_str()),
+        _ => {
+            return BuiltinResult {
+                stdout: String::new(),
+                stderr: String::from("Usage:\n  bg [%job]    # resume job in background\n"),
+                code: 1,
+            };
+        }
+    };
+    let job = match shell.jobs.find(spec) {
+        Ok(id) => shell.jobs.get_mut(id),
+        Err(msg) => {
+            return BuiltinResult {
+                stdout: String::new(),
+                stderr: format!("bg: {}\n", msg),
+                code: 1,
+            };
+        }
+    };
+    let Some(job) = job else {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from("bg: no current job\n"),
+            code: 1,
+        };
+    };
+
+    if job.state() != JobState::Stopped {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: format!("bg: job {} already in background\n", job.id),
+            code: 0,
+        };
+    }
+    job.resume();
+    BuiltinResult {
+        stdout: format!("[{}] {} &\n", job.id, job.command),
+        stderr: String::new(),
+        code: 0,
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, BuiltinResult, Streams};
 use crate::shell::Shell;
 
 pub struct CdCmd;
@@ -13,8 +13,8 @@ impl Builtin for CdCmd {
         "cd"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        cd(argv, shell)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(cd(argv, shell))
     }
 }
 
@@ -23,12 +23,12 @@ fn cd(args: &[String], sh: &mut Shell) -> BuiltinResult {
         // cd <dir>
         [d] => d.to_string(),
         // cd  （HOMEへ）
-        [] => match env::var("HOME") {
-            Ok(h) => h,
-            Err(e) => {
+        [] => match sh.variables.get("HOME") {
+            Some(h) => h.to_string(),
+            None => {
                 return BuiltinResult {
                     stdout: String::new(),
-                    stderr: format!("cd: HOME is not set: {}\n", e),
+                    stderr: String::from("cd: HOME is not set\n"),
                     code: 1,
                 };
             }
@@ -47,7 +47,8 @@ fn cd(args: &[String], sh: &mut Shell) -> BuiltinResult {
 
     match (env::set_current_dir(path), current_dir) {
         (Ok(()), Some(prev)) => {
-            sh.dir_stack.push(prev);
+            sh.dir_stack.push(prev.clone());
+            sh.changed_dir(prev);
             BuiltinResult {
                 stdout: String::new(),
                 stderr: String::new(),
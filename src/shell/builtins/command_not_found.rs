@@ -0,0 +1,53 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::Shell;
+
+pub struct CommandNotFoundCmd;
+
+impl Builtin for CommandNotFoundCmd {
+    fn name(&self) -> &'static str {
+        "command_not_found"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(command_not_found_with_args(shell, argv))
+    }
+}
+
+/// コマンドが見つからないときに実行するハンドラを登録する（ハンドラの引数の後ろにコマンド名と引数がつく）
+fn command_not_found_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    match args {
+        // 登録済みのハンドラ
+        [] => BuiltinResult {
+            stdout: shell
+                .not_found_handler
+                .as_ref()
+                .map(|h| format!("{}\n", h.join(" ")))
+                .unwrap_or_default(),
+            stderr: String::new(),
+            code: 0,
+        },
+        [opt] if opt == "--clear" => {
+            shell.not_found_handler = None;
+            ok()
+        }
+        [opt, ..] if opt.starts_with('-') => BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from(
+                "Usage:\n  command_not_found              # show\n  command_not_found <command...>\n  command_not_found --clear\n",
+            ),
+            code: 1,
+        },
+        command => {
+            shell.not_found_handler = Some(command.to_vec());
+            ok()
+        }
+    }
+}
+
+fn ok() -> BuiltinResult {
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code: 0,
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::BTreeSet;
 use std::process::{Command, Stdio};
 
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, BuiltinResult, Streams};
 use crate::shell::Shell;
 
 pub struct CompleteCmd;
@@ -11,77 +11,79 @@ impl Builtin for CompleteCmd {
         "complete"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        // 引数はコマンド名のみ
-        if argv.len() != 1 {
-            return usage();
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(complete_with_args(shell, argv))
+    }
+}
+
+fn complete_with_args(shell: &mut Shell, argv: &[String]) -> BuiltinResult {
+    // 引数はコマンド名のみ
+    if argv.len() != 1 {
+        return usage();
+    }
+    let cmd = argv[0].clone();
+    let mut warnings = Vec::new();
+
+    // ルート：man優先（抽出が空なら --help）
+    let (root_opts, mut root_subs) = match fetch_best(&[cmd.as_str()], &mut warnings) {
+        Some((opts, subs, _)) => (opts, subs),
+        None => {
+            return BuiltinResult {
+                stdout: String::new(),
+                stderr: format!("complete: no usable help/man content for `{}`\n", cmd),
+                code: 1,
+            };
         }
-        let cmd = argv[0].clone();
-        let mut warnings = Vec::new();
-
-        // ルート：man優先（抽出が空なら --help）
-        let (root_opts, mut root_subs) = match fetch_best(&[cmd.as_str()], &mut warnings) {
-            Some((opts, subs, _)) => (opts, subs),
-            None => {
-                return BuiltinResult {
-                    stdout: String::new(),
-                    stderr: format!("complete: no usable help/man content for `{}`\n", cmd),
-                    code: 1,
-                };
-            }
-        };
-
-        // --- Git 特化のサブコマンド救済 ---
-        // git の man/help は pager に流れたり、カテゴリ別の見出しで抽出しづらい場合がある。
-        // 抽出ゼロ/少数のときは `git --list-cmds` / `git help -a` を使って補う。
-        if cmd == "git" && root_subs.len() < 10 {
-            match fetch_git_subcommands() {
-                Ok(extra) if !extra.is_empty() => {
-                    root_subs.extend(extra);
-                }
-                Ok(_) => {}
-                Err(e) => {
-                    warnings.push(format!("(warn) failed to enumerate git subcommands: {e}\n"))
-                }
+    };
+
+    // --- Git 特化のサブコマンド救済 ---
+    // git の man/help は pager に流れたり、カテゴリ別の見出しで抽出しづらい場合がある。
+    // 抽出ゼロ/少数のときは `git --list-cmds` / `git help -a` を使って補う。
+    if cmd == "git" && root_subs.len() < 10 {
+        match fetch_git_subcommands() {
+            Ok(extra) if !extra.is_empty() => {
+                root_subs.extend(extra);
             }
+            Ok(_) => {}
+            Err(e) => warnings.push(format!("(warn) failed to enumerate git subcommands: {e}\n")),
         }
+    }
 
-        // 保存（トップレベル）
-        {
-            let centry = shell.completion.data.entry(cmd.clone()).or_default();
-            for o in &root_opts {
-                centry.options.insert(o.clone());
-            }
-            for s in &root_subs {
-                centry.subcommands.entry(s.clone()).or_default();
-            }
+    // 保存（トップレベル）
+    {
+        let centry = shell.completion.data.entry(cmd.clone()).or_default();
+        for o in &root_opts {
+            centry.options.insert(o.clone());
+        }
+        for s in &root_subs {
+            centry.subcommands.entry(s.clone()).or_default();
         }
+    }
 
-        // --- サブコマンドは1段のみ ---
-        for sub in &root_subs {
-            let segs = [cmd.as_str(), sub.as_str()];
-            if let Some((sub_opts, _subsubs, _src)) = fetch_best(&segs, &mut warnings) {
-                let centry = shell.completion.data.entry(cmd.clone()).or_default();
-                let sentry = centry.subcommands.entry(sub.clone()).or_default();
-                for o in &sub_opts {
-                    sentry.options.insert(o.clone());
-                }
+    // --- サブコマンドは1段のみ ---
+    for sub in &root_subs {
+        let segs = [cmd.as_str(), sub.as_str()];
+        if let Some((sub_opts, _subsubs, _src)) = fetch_best(&segs, &mut warnings) {
+            let centry = shell.completion.data.entry(cmd.clone()).or_default();
+            let sentry = centry.subcommands.entry(sub.clone()).or_default();
+            for o in &sub_opts {
+                sentry.options.insert(o.clone());
             }
         }
+    }
 
-        if let Err(e) = shell.completion.save() {
-            warnings.push(format!("(warning) failed to save completion DB: {e}"));
-        }
+    if let Err(e) = shell.completion.save() {
+        warnings.push(format!("(warning) failed to save completion DB: {e}"));
+    }
 
-        BuiltinResult {
-            stdout: format!("complete: updated `{}`\n", cmd),
-            stderr: if warnings.is_empty() {
-                String::new()
-            } else {
-                warnings.join("\n")
-            },
-            code: 0,
-        }
+    BuiltinResult {
+        stdout: format!("complete: updated `{}`\n", cmd),
+        stderr: if warnings.is_empty() {
+            String::new()
+        } else {
+            warnings.join("\n")
+        },
+        code: 0,
     }
 }
 
@@ -0,0 +1,52 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::Shell;
+
+pub struct DisownCmd;
+
+impl Builtin for DisownCmd {
+    fn name(&self) -> &'static str {
+        "disown"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(disown_with_args(shell, argv))
+    }
+}
+
+fn disown_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    let ids = match args {
+        [opt] if opt == "-a" => shell.jobs.ids(),
+        [] => match shell.jobs.find(None) {
+            Ok(id) => vec![id],
+            Err(msg) => return error(msg),
+        },
+        specs => {
+            let mut ids = Vec::new();
+            for spec in specs {
+                match shell.jobs.find(Some(spec)) {
+                    Ok(id) => ids.push(id),
+                    Err(msg) => return error(msg),
+                }
+            }
+            ids
+        }
+    };
+
+    // テーブルから外すだけ。プロセスはそのまま走り続ける
+    for id in ids {
+        shell.jobs.remove(id);
+    }
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code: 0,
+    }
+}
+
+fn error(msg: String) -> BuiltinResult {
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: format!("disown: {}\n", msg),
+        code: 1,
+    }
+}
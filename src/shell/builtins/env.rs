@@ -0,0 +1,53 @@
+use std::io::Write;
+
+use super::{Builtin, Streams};
+use crate::{
+    execute_argv,
+    pipeline::is_var_name,
+    shell::{Shell, variables::Scope},
+};
+
+pub struct EnvCmd;
+
+impl Builtin for EnvCmd {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        // 先頭の NAME=value はこの env の中だけエクスポートする
+        let split = argv
+            .iter()
+            .position(|arg| {
+                !arg.split_once('=')
+                    .is_some_and(|(name, _)| is_var_name(name))
+            })
+            .unwrap_or(argv.len());
+        let (assigns, command) = argv.split_at(split);
+
+        shell.variables.push_scope();
+        for (name, value) in assigns.iter().filter_map(|arg| arg.split_once('=')) {
+            shell
+                .variables
+                .set_in(Scope::Local, name, value.to_string(), Some(true));
+        }
+        let code = if command.is_empty() {
+            // 子プロセスに渡す環境変数の一覧
+            for (name, value) in shell.variables.exported() {
+                let _ = writeln!(io.stdout, "{}={}", name, value);
+            }
+            let _ = io.stdout.flush();
+            0
+        } else {
+            match execute_argv(command, shell) {
+                Ok(code) => code,
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "env: {}", e);
+                    1
+                }
+            }
+        };
+        shell.variables.pop_scope();
+        code
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, BuiltinResult, Streams};
 use crate::shell::Shell;
 
 pub struct ExitCmd;
@@ -8,25 +8,48 @@ impl Builtin for ExitCmd {
         "exit"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        exit_with_args(shell, argv)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(exit_with_args(shell, argv))
     }
 }
 
 fn exit_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
-    match args {
-        [] => {
-            shell.request_exit();
-            BuiltinResult {
-                stdout: String::new(),
-                stderr: String::new(),
-                code: 0,
-            }
-        }
-        _ => BuiltinResult {
-            stdout: String::new(),
-            stderr: String::from("Usage:\n  exit    # no args\n"),
-            code: 1,
+    // 引数がなければ直前の終了コードで終わる
+    let code = match args {
+        [] => shell.status,
+        [code] => match code.parse::<i32>() {
+            Ok(n) => n & 0xff,
+            Err(_) => return error(format!("{}: numeric argument required", code), 2),
         },
+        _ => return error("too many arguments".into(), 1),
+    };
+
+    // ジョブが残っていれば 1 度だけ警告して止める（もう一度 exit すれば終わる）。
+    // ユニバーサル変数は set -U のたびにファイルへ書き、履歴と補完は Drop で必ず保存するので、
+    // 終了で失われる未保存の状態はジョブのほかにない
+    if shell.interactive && !shell.exit_warned {
+        let jobs = shell.jobs.active_count();
+        if jobs > 0 {
+            shell.exit_warned = true;
+            return error(
+                format!("there are {} background jobs; run exit again to quit", jobs),
+                1,
+            );
+        }
+    }
+
+    shell.request_exit(code);
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code,
+    }
+}
+
+fn error(msg: String, code: i32) -> BuiltinResult {
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: format!("exit: {}\n", msg),
+        code,
     }
 }
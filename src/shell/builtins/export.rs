@@ -0,0 +1,52 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::{pipeline::is_var_name, shell::Shell};
+
+pub struct ExportCmd;
+
+impl Builtin for ExportCmd {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(export(argv, shell))
+    }
+}
+
+/// `export NAME=value` / `export NAME`（今の値のままエクスポートする）
+fn export(args: &[String], shell: &mut Shell) -> BuiltinResult {
+    if args.is_empty() {
+        let stdout = shell
+            .variables
+            .exported()
+            .into_iter()
+            .map(|(name, value)| format!("{}={}\n", name, value))
+            .collect();
+        return BuiltinResult {
+            stdout,
+            stderr: String::new(),
+            code: 0,
+        };
+    }
+
+    let mut stderr = String::new();
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_var_name(name) {
+            stderr += &format!("export: {}: invalid variable name\n", name);
+            continue;
+        }
+        if let Some(value) = value {
+            shell.variables.set(name, value.to_string());
+        }
+        shell.variables.set_exported(name, true);
+    }
+    BuiltinResult {
+        stdout: String::new(),
+        code: if stderr.is_empty() { 0 } else { 1 },
+        stderr,
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Write};
+
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::{Shell, jobs};
+
+pub struct FgCmd;
+
+impl Builtin for FgCmd {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(fg_with_args(shell, argv))
+    }
+}
+
+fn fg_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    let spec = match args {
+        [] => None,
+        [spec] => Some(spec.as_str()),
+        _ => {
+            return BuiltinResult {
+                stdout: String::new(),
+                stderr: String::from("Usage:\n  fg [%job]    # resume job in foreground\n"),
+                code: 1,
+            };
+        }
+    };
+    if !shell.job_control {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from("fg: no job control\n"),
+            code: 1,
+        };
+    }
+    let job = match shell.jobs.find(spec) {
+        Ok(id) => shell.jobs.remove(id),
+        Err(msg) => {
+            return BuiltinResult {
+                stdout: String::new(),
+                stderr: format!("fg: {}\n", msg),
+                code: 1,
+            };
+        }
+    };
+    let Some(mut job) = job else {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from("fg: no current job\n"),
+            code: 1,
+        };
+    };
+
+    // 待っている間に出力されるので、コマンド名はここで直接出す
+    println!("{}", job.command);
+    let _ = io::stdout().flush();
+    // 端末を渡してから再開する（先に再開すると端末を読もうとして止まる）
+    jobs::give_terminal(job.pgid);
+    job.resume();
+    let code = match shell.jobs.foreground(job, true) {
+        Some(codes) => codes.last().copied().unwrap_or(0),
+        None => 128 + libc::SIGTSTP,
+    };
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code,
+    }
+}
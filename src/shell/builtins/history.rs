@@ -1,5 +1,7 @@
+use std::io::Write;
+
 use super::Shell;
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, Streams};
 use crate::shell::history::History;
 
 pub struct HistoryCmd;
@@ -9,39 +11,45 @@ impl Builtin for HistoryCmd {
         "history"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        show_history_with_args(&shell.history, argv)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        show_history_with_args(&shell.history, argv, io)
     }
 }
 
-fn show_history_with_args(history: &History, args: &[String]) -> BuiltinResult {
-    match args {
-        [] => show_history(history),
-        _ => BuiltinResult {
-            stdout: String::new(),
-            stderr: String::from("Usage:\n  history    # Show history\n"),
-            code: 1,
-        },
+/// `history [word...]` / `cmd | history`。語を与えると、すべてを含む履歴だけを表示する
+fn show_history_with_args(history: &History, args: &[String], io: &mut Streams) -> i32 {
+    if args.first().is_some_and(|a| a.starts_with('-')) {
+        let _ = write!(
+            io.stderr,
+            "Usage:\n  history [word...]    # Show history\n  cmd | history        # Filter by words from stdin\n"
+        );
+        return 1;
+    }
+    let mut words = args.to_vec();
+    // 前の段からパイプで渡された入力も絞り込みの語にする（-c やスクリプトが引き継いだ stdin は読まない）
+    if words.is_empty() && io.piped {
+        let mut input = String::new();
+        let _ = io.stdin.read_to_string(&mut input);
+        words = input.split_whitespace().map(String::from).collect();
     }
+    show_history(history, &words, io)
 }
 
-fn show_history(history: &History) -> BuiltinResult {
+fn show_history(history: &History, words: &[String], io: &mut Streams) -> i32 {
     if history.log.is_empty() {
-        return BuiltinResult {
-            stdout: String::from("(no history)\n"),
-            stderr: String::new(),
-            code: 0,
-        };
+        let _ = writeln!(io.stdout, "(no history)");
+        return 0;
     }
 
-    let mut stdout = String::new();
+    // 1 件ずつ書き出す（`| head` で読み手が終われば止める）
     for (idx, entry) in &history.log {
-        stdout.push_str(&format!("{idx}: {entry}\n"));
-    }
-
-    BuiltinResult {
-        stdout,
-        stderr: String::new(),
-        code: 0,
+        if !words.iter().all(|w| entry.contains(w.as_str())) {
+            continue;
+        }
+        if writeln!(io.stdout, "{idx}: {entry}").is_err() {
+            break;
+        }
     }
+    let _ = io.stdout.flush();
+    0
 }
@@ -0,0 +1,51 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::Shell;
+
+pub struct JobsCmd;
+
+impl Builtin for JobsCmd {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(jobs_with_args(shell, argv))
+    }
+}
+
+fn jobs_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    let lines = match args {
+        [] => shell.jobs.listing(false),
+        [opt] if opt == "-l" => shell.jobs.listing(true),
+        [opt] if opt == "-p" => {
+            shell.jobs.reap();
+            shell
+                .jobs
+                .ids()
+                .into_iter()
+                .filter_map(|id| shell.jobs.get(id))
+                .map(|job| job.pgid.to_string())
+                .collect()
+        }
+        _ => {
+            return BuiltinResult {
+                stdout: String::new(),
+                stderr: String::from(
+                    "Usage:\n  jobs       # list jobs\n  jobs -l    # with pids\n  jobs -p    # process group ids only\n",
+                ),
+                code: 1,
+            };
+        }
+    };
+
+    let mut stdout = String::new();
+    for line in lines {
+        stdout.push_str(&line);
+        stdout.push('\n');
+    }
+    BuiltinResult {
+        stdout,
+        stderr: String::new(),
+        code: 0,
+    }
+}
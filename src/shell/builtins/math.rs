@@ -0,0 +1,37 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::{pipeline::eval_arith, shell::Shell};
+
+pub struct MathCmd;
+
+impl Builtin for MathCmd {
+    fn name(&self) -> &'static str {
+        "math"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(math_with_args(shell, argv))
+    }
+}
+
+fn math_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    if args.is_empty() {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from("Usage:\n  math <expression>    # e.g. math '(1 + 2) * 3'\n"),
+            code: 1,
+        };
+    }
+    // 複数の引数は空白でつないで 1 つの式とする
+    match eval_arith(&args.join(" "), shell) {
+        Ok(n) => BuiltinResult {
+            stdout: format!("{}\n", n),
+            stderr: String::new(),
+            code: 0,
+        },
+        Err(msg) => BuiltinResult {
+            stdout: String::new(),
+            stderr: format!("math: {}\n", msg),
+            code: 1,
+        },
+    }
+}
@@ -1,15 +1,32 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    os::fd::FromRawFd,
+};
+
 use crate::shell::Shell;
 
 mod abbr;
 mod alias;
+mod bg;
 mod cd;
+mod command_not_found;
 mod complete;
+mod disown;
+mod env;
 mod exit;
+mod export;
+mod fg;
 mod history;
+mod jobs;
+mod math;
+mod on_exit;
 mod popd;
 mod set;
 mod setenv;
 mod source;
+mod unset;
+mod wait;
 
 pub use source::source_with_io;
 
@@ -21,7 +38,52 @@ pub struct BuiltinResult {
 
 pub trait Builtin {
     fn name(&self) -> &'static str;
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult;
+    /// 入出力は io へ読み書きし、終了コードを返す
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32;
+}
+
+/// ビルトインの標準入出力。パイプやリダイレクトは実行前に fd 0/1/2 へ適用してある
+pub struct Streams {
+    pub stdin: Box<dyn BufRead>,
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    pub piped: bool, // stdin がパイプラインの前の段の出力（リダイレクトや引き継いだ stdin では false）
+}
+
+impl Streams {
+    /// 今の fd 0/1/2 につなぐ。stdin は複製した fd から読むので、読み残しを次のコマンドへ持ち越さない
+    pub fn std() -> Self {
+        let fd = unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10) };
+        let stdin: Box<dyn BufRead> = if fd < 0 {
+            // `<&-` で閉じられている
+            Box::new(io::empty())
+        } else {
+            Box::new(BufReader::new(unsafe { File::from_raw_fd(fd) }))
+        };
+        Self {
+            stdin,
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            piped: false,
+        }
+    }
+
+    /// std() に、前の段からパイプで読むかを添える
+    pub fn std_piped(piped: bool) -> Self {
+        Self {
+            piped,
+            ..Self::std()
+        }
+    }
+
+    /// 出力をまとめて返すビルトインの結果を書き出し、終了コードを返す
+    pub fn emit(&mut self, ret: BuiltinResult) -> i32 {
+        // 読み手が先に終わっていても（`| head` など）終了コードはビルトインのもの
+        let _ = self.stdout.write_all(ret.stdout.as_bytes());
+        let _ = self.stdout.flush();
+        let _ = self.stderr.write_all(ret.stderr.as_bytes());
+        ret.code
+    }
 }
 
 // すべての builtin を登録
@@ -37,6 +99,17 @@ fn registry() -> &'static [&'static dyn Builtin] {
         &setenv::SetenvCmd,
         &source::SourceCmd,
         &complete::CompleteCmd,
+        &jobs::JobsCmd,
+        &fg::FgCmd,
+        &bg::BgCmd,
+        &wait::WaitCmd,
+        &disown::DisownCmd,
+        &math::MathCmd,
+        &on_exit::OnExitCmd,
+        &command_not_found::CommandNotFoundCmd,
+        &export::ExportCmd,
+        &unset::UnsetCmd,
+        &env::EnvCmd,
     ]
 }
 
@@ -0,0 +1,53 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::Shell;
+
+pub struct OnExitCmd;
+
+impl Builtin for OnExitCmd {
+    fn name(&self) -> &'static str {
+        "on_exit"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(on_exit_with_args(shell, argv))
+    }
+}
+
+/// シェルの終了時（履歴を保存する前）に実行するコマンドを登録する
+fn on_exit_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    match args {
+        // 登録済みの一覧
+        [] => BuiltinResult {
+            stdout: shell
+                .exit_hooks
+                .iter()
+                .map(|h| format!("{}\n", h.join(" ")))
+                .collect(),
+            stderr: String::new(),
+            code: 0,
+        },
+        [opt] if opt == "--clear" => {
+            shell.exit_hooks.clear();
+            ok()
+        }
+        [opt, ..] if opt.starts_with('-') => BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from(
+                "Usage:\n  on_exit              # list\n  on_exit <command...>\n  on_exit --clear\n",
+            ),
+            code: 1,
+        },
+        command => {
+            shell.exit_hooks.push(command.to_vec());
+            ok()
+        }
+    }
+}
+
+fn ok() -> BuiltinResult {
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code: 0,
+    }
+}
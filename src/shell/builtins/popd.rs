@@ -1,6 +1,6 @@
 use std::env;
 
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, BuiltinResult, Streams};
 use crate::shell::Shell;
 
 pub struct PopdCmd;
@@ -10,8 +10,8 @@ impl Builtin for PopdCmd {
         "popd"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        popd_with_args(shell, argv)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(popd_with_args(shell, argv))
     }
 }
 
@@ -35,9 +35,13 @@ fn popd(shell: &mut Shell) -> BuiltinResult {
         };
     };
 
+    let current_dir = env::current_dir().ok();
     match env::set_current_dir(&dir) {
         Ok(()) => {
             let _ = shell.dir_stack.pop();
+            if let Some(prev) = current_dir {
+                shell.changed_dir(prev);
+            }
             BuiltinResult {
                 stdout: String::new(),
                 stderr: String::new(),
@@ -1,7 +1,11 @@
-use std::collections::BTreeMap;
-
-use super::{Builtin, BuiltinResult};
-use crate::shell::Shell;
+use super::{Builtin, BuiltinResult, Streams};
+use crate::{
+    pipeline::is_var_name,
+    shell::{
+        NoMatch, Shell, ShellOptions,
+        variables::{Scope, Variables},
+    },
+};
 
 pub struct SetCmd;
 
@@ -10,25 +14,244 @@ impl Builtin for SetCmd {
         "set"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        set(argv, &mut shell.variables)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(set(argv, shell))
     }
 }
 
-fn set(args: &[String], variables: &mut BTreeMap<String, String>) -> BuiltinResult {
+fn set(args: &[String], shell: &mut Shell) -> BuiltinResult {
     match args {
-        [key, value] => {
-            variables.insert(key.clone(), value.clone());
+        // 変数の一覧
+        [] => BuiltinResult {
+            stdout: list_variables(&shell.variables, false),
+            stderr: String::new(),
+            code: 0,
+        },
+        // set -o / set +o  （オプション一覧）
+        [flag] if flag == "-o" || flag == "+o" => BuiltinResult {
+            stdout: list_options(&shell.options),
+            stderr: String::new(),
+            code: 0,
+        },
+        // set -o <option> / set +o <option>
+        [flag, name] if flag == "-o" || flag == "+o" => {
+            let on = flag == "-o";
+            match option_mut(&mut shell.options, name) {
+                Some(opt) => {
+                    *opt = on;
+                    ok()
+                }
+                None => BuiltinResult {
+                    stdout: String::new(),
+                    stderr: format!("set: {}: invalid option name\n", name),
+                    code: 1,
+                },
+            }
+        }
+        // set -o nomatch <error|passthrough|null>
+        [flag, name, mode] if flag == "-o" && name == "nomatch" => match NoMatch::from_name(mode) {
+            Some(mode) => {
+                shell.options.nomatch = mode;
+                ok()
+            }
+            None => BuiltinResult {
+                stdout: String::new(),
+                stderr: format!(
+                    "set: nomatch: {}: expected error, passthrough or null\n",
+                    mode
+                ),
+                code: 1,
+            },
+        },
+        _ => set_variable(args, shell),
+    }
+}
+
+/// `set [-l|-g|-U] [-x|-u] <variable> <value...>` / `set -e [-U] <variable...>`
+fn set_variable(args: &[String], shell: &mut Shell) -> BuiltinResult {
+    let mut scope = None;
+    let mut universal = false;
+    let mut export = None;
+    let mut erase = false;
+    let mut rest = args;
+    while let [flag, tail @ ..] = rest
+        && flag.len() > 1
+        && flag.starts_with('-')
+    {
+        rest = tail;
+        if flag == "--" {
+            break;
+        }
+        for c in flag[1..].chars() {
+            match c {
+                'l' => scope = Some(Scope::Local),
+                'g' => scope = Some(Scope::Global),
+                'U' => universal = true,
+                'x' => export = Some(true),
+                'u' => export = Some(false),
+                'e' => erase = true,
+                _ => return usage(),
+            }
+        }
+    }
+    // 名前を並べるのは -e だけ。それ以外は先頭が名前で残りが値
+    let names = if erase {
+        rest
+    } else {
+        &rest[..rest.len().min(1)]
+    };
+    if let Some(name) = names.iter().find(|name| !is_var_name(name)) {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: format!("set: {}: invalid variable name\n", name),
+            code: 1,
+        };
+    }
+
+    if universal {
+        return set_universal(rest, erase, export, shell);
+    }
+
+    match rest {
+        // set -e a b  （どれか無ければ 1）
+        names if erase => {
+            let mut code = 0;
+            for name in names {
+                if !shell.variables.erase(name) {
+                    code = 1;
+                }
+            }
             BuiltinResult {
                 stdout: String::new(),
                 stderr: String::new(),
-                code: 0,
+                code,
+            }
+        }
+        // set -x  （エクスポートした変数の一覧）
+        [] if export == Some(true) => BuiltinResult {
+            stdout: list_variables(&shell.variables, true),
+            stderr: String::new(),
+            code: 0,
+        },
+        [] => usage(),
+        // set -x name  （値はそのままでエクスポートだけ切り替える）
+        [name] if scope.is_none() && export.is_some() => {
+            shell.variables.set_exported(name, export == Some(true));
+            ok()
+        }
+        [name, values @ ..] => {
+            let value = values.join(" ");
+            match scope {
+                Some(scope) => shell.variables.set_in(scope, name, value, export),
+                None => {
+                    shell.variables.set(name, value);
+                    if let Some(export) = export {
+                        shell.variables.set_exported(name, export);
+                    }
+                }
             }
+            ok()
         }
-        _ => BuiltinResult {
+    }
+}
+
+/// `set -U`: ほかのシェルと共有する変数をファイルに書く
+fn set_universal(
+    rest: &[String],
+    erase: bool,
+    export: Option<bool>,
+    shell: &mut Shell,
+) -> BuiltinResult {
+    let variables = &mut shell.variables;
+    let result = match rest {
+        // set -eU a b  （どれか無ければ 1）
+        names if erase => names.iter().try_fold(true, |found, name| {
+            Ok(variables.erase_universal(name)? && found)
+        }),
+        // set -U  （一覧）
+        [] => {
+            return BuiltinResult {
+                stdout: variables
+                    .list_universal()
+                    .into_iter()
+                    .map(|(name, value, _)| format!("{} {}\n", name, value))
+                    .collect(),
+                stderr: String::new(),
+                code: 0,
+            };
+        }
+        // set -Ux name  （値はそのままでエクスポートだけ切り替える）
+        [name] if export.is_some() => {
+            let value = variables
+                .list_universal()
+                .into_iter()
+                .find(|(n, _, _)| n == name)
+                .map(|(_, value, _)| value.to_string())
+                .unwrap_or_default();
+            variables.set_universal(name, value, export).map(|()| true)
+        }
+        [name, values @ ..] => variables
+            .set_universal(name, values.join(" "), export)
+            .map(|()| true),
+    };
+    match result {
+        Ok(true) => ok(),
+        Ok(false) => BuiltinResult {
+            stdout: String::new(),
+            stderr: String::new(),
+            code: 1,
+        },
+        Err(e) => BuiltinResult {
             stdout: String::new(),
-            stderr: String::from("Usage:\n  set <variable> <value>\n"),
+            stderr: format!("set: cannot save universal variables: {}\n", e),
             code: 1,
         },
     }
 }
+
+fn list_variables(variables: &Variables, exported_only: bool) -> String {
+    variables
+        .list()
+        .into_iter()
+        .filter(|(_, _, exported)| *exported || !exported_only)
+        .map(|(name, value, _)| format!("{} {}\n", name, value))
+        .collect()
+}
+
+fn usage() -> BuiltinResult {
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::from(
+            "Usage:\n  set                                  # list variables\n  set [-l|-g|-U] [-x|-u] <variable> <value...>\n  set [-U] -x|-u <variable>            # export / unexport\n  set -e [-U] <variable...>            # erase\n  set -U                               # list universal variables\n  set -o <option>    # enable option\n  set +o <option>    # disable option\n  set -o             # list options\n  set -o nomatch <error|passthrough|null>\n",
+        ),
+        code: 1,
+    }
+}
+
+fn option_mut<'a>(options: &'a mut ShellOptions, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "pipefail" => Some(&mut options.pipefail),
+        "dotglob" => Some(&mut options.dotglob),
+        _ => None,
+    }
+}
+
+fn list_options(options: &ShellOptions) -> String {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let list = [
+        ("pipefail", on_off(options.pipefail)),
+        ("dotglob", on_off(options.dotglob)),
+        ("nomatch", options.nomatch.name()),
+    ];
+    list.iter()
+        .map(|(name, value)| format!("{:<16}{}\n", name, value))
+        .collect()
+}
+
+fn ok() -> BuiltinResult {
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code: 0,
+    }
+}
@@ -1,5 +1,5 @@
-use super::{Builtin, BuiltinResult};
-use crate::shell::Shell;
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::{Shell, variables::Scope};
 
 pub struct SetenvCmd;
 
@@ -8,17 +8,18 @@ impl Builtin for SetenvCmd {
         "setenv"
     }
 
-    fn run(&self, _shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        set_env(argv)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(set_env(argv, shell))
     }
 }
 
-fn set_env(args: &[String]) -> BuiltinResult {
+/// `set -gx <variable> <value>` と同じ
+fn set_env(args: &[String], shell: &mut Shell) -> BuiltinResult {
     match args {
         [key, value] => {
-            unsafe {
-                std::env::set_var(key, value);
-            }
+            shell
+                .variables
+                .set_in(Scope::Global, key, value.clone(), Some(true));
             BuiltinResult {
                 stdout: String::new(),
                 stderr: String::new(),
@@ -1,10 +1,10 @@
-use super::{Builtin, BuiltinResult};
+use super::{Builtin, Streams};
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    fs,
+    io::{Read, Write},
 };
 
-use crate::{execute, parse, shell::Shell, tokenize};
+use crate::{execute, line_col, shell::Shell, statements, tokenize};
 
 pub struct SourceCmd;
 
@@ -13,83 +13,57 @@ impl Builtin for SourceCmd {
         "source"
     }
 
-    fn run(&self, shell: &mut Shell, argv: &[String]) -> BuiltinResult {
-        source_with_io(argv, shell)
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        source_with_io(argv, shell, io)
     }
 }
 
-pub fn source_with_io(args: &[String], shell: &mut Shell) -> BuiltinResult {
+pub fn source_with_io(args: &[String], shell: &mut Shell, io: &mut Streams) -> i32 {
     let path = match args {
         [path] => path,
         _ => {
-            return BuiltinResult {
-                stdout: String::new(),
-                stderr: String::from("Usage:\n  source <path>\n"),
-                code: 1,
-            };
+            let _ = write!(
+                io.stderr,
+                "Usage:\n  source <path>\n  source -    # read from stdin\n"
+            );
+            return 1;
         }
     };
 
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            return BuiltinResult {
-                stdout: String::new(),
-                stderr: format!("source: cannot open '{}': {}\n", path, e),
-                code: 1,
-            };
-        }
+    let mut content = String::new();
+    let read = if path == "-" {
+        io.stdin.read_to_string(&mut content)
+    } else {
+        fs::File::open(path).and_then(|mut f| f.read_to_string(&mut content))
     };
+    if let Err(e) = read {
+        let _ = writeln!(io.stderr, "source: cannot open '{}': {}", path, e);
+        return 1;
+    }
 
-    let reader = BufReader::new(file);
-    let mut last_status = 0;
-
-    for line_res in reader.lines() {
-        let line = match line_res {
-            Ok(s) => s,
-            Err(e) => {
-                return BuiltinResult {
-                    stdout: String::new(),
-                    stderr: format!("source: read error: {}\n", e),
-                    code: 1,
-                };
+    // 1 文ずつ解析して実行する。構文エラーの文は報告して飛ばし、残りの文は実行する
+    let tokens = tokenize(&content);
+    let mut code = 0;
+    shell.variables.push_scope(); // set -l の変数はこのファイルの中だけ
+    for statement in statements(&tokens) {
+        code = match statement.map(|expr| execute(&expr, shell)) {
+            Ok(Ok(code)) => code,
+            Ok(Err(e)) => {
+                let _ = writeln!(io.stderr, "source: execute error: {:?}", e);
+                1
             }
-        };
-
-        // 空行・（任意）コメント行はスキップ
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
-        }
-
-        // 1) tokenize
-        let tokens = tokenize(trimmed);
-
-        // 2) parse
-        let Ok(expr) = parse(&tokens) else {
-            return BuiltinResult {
-                stdout: String::new(),
-                stderr: format!("source: parse error in line: {}\n", trimmed),
-                code: 1,
-            };
-        };
-
-        // 5) execute
-        match execute(&expr, shell) {
-            Ok(code) => last_status = code,
             Err(e) => {
-                return BuiltinResult {
-                    stdout: String::new(),
-                    stderr: format!("source: execute error: {:?}\n", e),
-                    code: 1,
+                let _ = match e.position() {
+                    Some(at) => {
+                        let (line, col) = line_col(&content, at);
+                        writeln!(io.stderr, "source: {}:{}:{}: {}", path, line, col, e)
+                    }
+                    None => writeln!(io.stderr, "source: {}: {}", path, e),
                 };
+                1
             }
-        }
-    }
-
-    BuiltinResult {
-        stdout: String::new(),
-        stderr: String::new(),
-        code: last_status,
+        };
     }
+    shell.variables.pop_scope();
+    code
 }
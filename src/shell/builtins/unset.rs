@@ -0,0 +1,39 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::{pipeline::is_var_name, shell::Shell};
+
+pub struct UnsetCmd;
+
+impl Builtin for UnsetCmd {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(unset(argv, shell))
+    }
+}
+
+/// 変数を消す（`set -e` と違い、無い変数を指定してもエラーにしない）
+fn unset(args: &[String], shell: &mut Shell) -> BuiltinResult {
+    if args.is_empty() {
+        return BuiltinResult {
+            stdout: String::new(),
+            stderr: String::from("Usage:\n  unset <variable...>\n"),
+            code: 1,
+        };
+    }
+
+    let mut stderr = String::new();
+    for name in args {
+        if is_var_name(name) {
+            shell.variables.erase(name);
+        } else {
+            stderr += &format!("unset: {}: invalid variable name\n", name);
+        }
+    }
+    BuiltinResult {
+        stdout: String::new(),
+        code: if stderr.is_empty() { 0 } else { 1 },
+        stderr,
+    }
+}
@@ -0,0 +1,56 @@
+use super::{Builtin, BuiltinResult, Streams};
+use crate::shell::{Shell, jobs::JobState};
+
+pub struct WaitCmd;
+
+impl Builtin for WaitCmd {
+    fn name(&self) -> &'static str {
+        "wait"
+    }
+
+    fn run(&self, shell: &mut Shell, argv: &[String], io: &mut Streams) -> i32 {
+        io.emit(wait_with_args(shell, argv))
+    }
+}
+
+fn wait_with_args(shell: &mut Shell, args: &[String]) -> BuiltinResult {
+    // 引数がなければ全ジョブを待つ
+    let ids = if args.is_empty() {
+        shell.jobs.ids()
+    } else {
+        let mut ids = Vec::new();
+        for spec in args {
+            match shell.jobs.find(Some(spec)) {
+                Ok(id) => ids.push(id),
+                Err(msg) => {
+                    return BuiltinResult {
+                        stdout: String::new(),
+                        stderr: format!("wait: {}\n", msg),
+                        code: 127,
+                    };
+                }
+            }
+        }
+        ids
+    };
+
+    let mut code = 0;
+    for id in ids {
+        let Some(job) = shell.jobs.get_mut(id) else {
+            continue;
+        };
+        job.wait();
+        match job.state() {
+            JobState::Done(c) => {
+                code = c;
+                shell.jobs.remove(id);
+            }
+            JobState::Stopped | JobState::Running => code = 128 + libc::SIGTSTP,
+        }
+    }
+    BuiltinResult {
+        stdout: String::new(),
+        stderr: String::new(),
+        code,
+    }
+}
@@ -1,4 +1,9 @@
-use std::{collections::BTreeSet, env, fs::read_dir, os::unix::fs::PermissionsExt, path::Path};
+use std::{
+    collections::BTreeSet,
+    fs::{self, read_dir},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
 
 use crate::shell::builtins;
 
@@ -25,8 +30,9 @@ impl ExeList {
         self.extra_entries.insert(executable);
     }
 
-    pub fn command_candidates(&mut self, prefix: &str) -> Vec<String> {
-        self.refresh_path_entries();
+    /// path は $PATH の値
+    pub fn command_candidates(&mut self, prefix: &str, path: &str) -> Vec<String> {
+        self.refresh_path_entries(path);
         let mut combined = BTreeSet::new();
         combined.extend(self.path_entries.iter().cloned());
         combined.extend(self.extra_entries.iter().cloned());
@@ -36,12 +42,57 @@ impl ExeList {
             .collect()
     }
 
-    fn refresh_path_entries(&mut self) {
-        let path_env = env::var("PATH").unwrap_or_default();
+    /// 打ち間違いらしい name に近いコマンド名（編集距離の近い順に最大 3 つ）
+    pub fn suggestions(&mut self, name: &str, path: &str) -> Vec<String> {
+        let limit = name.chars().count() / 4 + 1;
+        let mut near: Vec<(usize, String)> = self
+            .command_candidates("", path)
+            .into_iter()
+            .filter_map(|c| {
+                let d = edit_distance(name, &c);
+                (d <= limit).then_some((d, c))
+            })
+            .collect();
+        near.sort();
+        near.into_iter().take(3).map(|(_, c)| c).collect()
+    }
+
+    /// name を外部コマンドとして実行できなければ、bash と同じ終了コードと理由を返す
+    pub fn unrunnable(name: &str, path: &str) -> Option<(i32, &'static str)> {
+        if name.contains('/') {
+            return match fs::metadata(name) {
+                Err(_) => Some((127, "no such file or directory")),
+                Ok(m) if m.is_dir() => Some((126, "is a directory")),
+                Ok(m) if m.permissions().mode() & 0o111 == 0 => Some((126, "permission denied")),
+                Ok(_) => None,
+            };
+        }
+        let mut denied = false;
+        for dir in path.split(':') {
+            if dir.is_empty() {
+                continue;
+            }
+            if let Ok(m) = fs::metadata(Path::new(dir).join(name))
+                && !m.is_dir()
+            {
+                if m.permissions().mode() & 0o111 != 0 {
+                    return None;
+                }
+                denied = true;
+            }
+        }
+        if denied {
+            Some((126, "permission denied"))
+        } else {
+            Some((127, "command not found"))
+        }
+    }
+
+    fn refresh_path_entries(&mut self, path_env: &str) {
         if path_env == self.pre_path {
             return;
         }
-        self.pre_path = path_env.clone();
+        self.pre_path = path_env.to_string();
         self.path_entries.clear();
         for dir in path_env.split(':') {
             if dir.is_empty() {
@@ -67,3 +118,27 @@ impl ExeList {
         }
     }
 }
+
+/// 隣り合う文字の入れ替えも 1 と数える編集距離（gti → git が 1 になる）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j]: a[..i] と b[..j] の距離
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
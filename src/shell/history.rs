@@ -30,9 +30,19 @@ impl History {
         if !Path::new(&path).is_file() {
             File::create(&path).unwrap();
         }
-        let mut command_log = VecDeque::new();
+        let mut command_log: VecDeque<(String, String)> = VecDeque::new();
         let mut hash = BTreeSet::new();
         for line in fs::read_to_string(&path).unwrap().split("\n") {
+            // タブで始まる行は直前のコマンドの続きの行
+            if let Some(rest) = line.strip_prefix('\t')
+                && let Some((pwd, cmd)) = command_log.pop_back()
+            {
+                hash.remove(&(pwd.clone(), cmd.clone()));
+                let cmd = cmd + "\n" + rest;
+                hash.insert((pwd.clone(), cmd.clone()));
+                command_log.push_back((pwd, cmd));
+                continue;
+            }
             if !line.contains(",") {
                 continue;
             }
@@ -53,6 +63,9 @@ impl History {
             buffer_r: vec![],
         }
     }
+    pub(super) fn path(&self) -> &str {
+        &self.log_path
+    }
     pub fn push(&mut self, cmd: String) {
         let pwd = match std::env::current_dir() {
             Ok(p) => p.to_string_lossy().into_owned(),
@@ -118,7 +131,7 @@ impl History {
             .log
             .iter()
             .cloned()
-            .map(|(pwd, cmd)| pwd + "," + &cmd)
+            .map(|(pwd, cmd)| pwd + "," + &cmd.replace('\n', "\n\t"))
             .collect::<Vec<_>>()
             .join("\n");
         fs::write(&self.log_path, log_str)?;
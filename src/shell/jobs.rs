@@ -0,0 +1,325 @@
+// ジョブ管理。パイプライン（またはバックグラウンドのサブシェル）1 つを 1 ジョブとし、
+// プロセスグループ単位で停止・再開する。
+use std::io;
+
+/// 制御端末として扱う fd
+const TTY: i32 = libc::STDIN_FILENO;
+
+/// 対話シェルならジョブ制御を有効にする（自分のプロセスグループを作って端末を握る）
+pub fn init_job_control() -> bool {
+    if unsafe { libc::isatty(TTY) } != 1 {
+        return false;
+    }
+    unsafe {
+        libc::setpgid(0, 0); // セッションリーダーなら失敗するが、その場合は既にグループを持っている
+        libc::tcsetpgrp(TTY, libc::getpgrp());
+    }
+    true
+}
+
+/// 端末のフォアグラウンドを pgid に渡す
+pub fn give_terminal(pgid: i32) {
+    unsafe {
+        libc::tcsetpgrp(TTY, pgid);
+    }
+}
+
+/// 端末のフォアグラウンドをシェル自身に戻す
+pub fn take_terminal() {
+    give_terminal(unsafe { libc::getpgrp() });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32), // 最後のプロセスの終了コード
+}
+
+struct Process {
+    pid: i32,
+    status: Option<i32>, // 終了していれば終了コード
+    stopped: bool,
+}
+
+pub struct Job {
+    pub id: usize,
+    pub pgid: i32, // 0 ならシェルと同じグループ（ジョブ制御なし）
+    pub command: String,
+    procs: Vec<Process>,
+    notified: bool, // 現在の状態を表示済みか
+}
+
+impl Job {
+    pub fn new(pgid: i32, pids: Vec<i32>, command: String) -> Self {
+        let procs = pids
+            .into_iter()
+            .map(|pid| Process {
+                pid,
+                status: None,
+                stopped: false,
+            })
+            .collect();
+        Self {
+            id: 0,
+            pgid,
+            command,
+            procs,
+            notified: false,
+        }
+    }
+
+    pub fn pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.procs.iter().map(|p| p.pid)
+    }
+
+    pub fn state(&self) -> JobState {
+        if self.procs.iter().all(|p| p.status.is_some()) {
+            JobState::Done(self.procs.last().and_then(|p| p.status).unwrap_or(0))
+        } else if self.procs.iter().all(|p| p.status.is_some() || p.stopped) {
+            JobState::Stopped
+        } else {
+            JobState::Running
+        }
+    }
+
+    /// SIGCONT を送って実行を再開する
+    pub fn resume(&mut self) {
+        unsafe {
+            if self.pgid > 0 {
+                libc::kill(-self.pgid, libc::SIGCONT);
+            } else {
+                for p in &self.procs {
+                    libc::kill(p.pid, libc::SIGCONT);
+                }
+            }
+        }
+        for p in &mut self.procs {
+            p.stopped = false;
+        }
+        self.notified = false;
+    }
+
+    /// 全プロセスの終了か、いずれかの停止まで待つ
+    pub fn wait(&mut self) {
+        for p in &mut self.procs {
+            while p.status.is_none() && !p.stopped {
+                let mut raw = 0;
+                let ret = unsafe { libc::waitpid(p.pid, &mut raw, libc::WUNTRACED) };
+                if ret < 0 {
+                    if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    // 既に回収済み（ECHILD）
+                    p.status = Some(0);
+                    break;
+                }
+                p.update(raw);
+            }
+            if p.stopped {
+                return;
+            }
+        }
+    }
+
+    /// 待たずに状態変化だけ拾う
+    fn poll(&mut self) {
+        for p in &mut self.procs {
+            while p.status.is_none() {
+                let mut raw = 0;
+                let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+                let ret = unsafe { libc::waitpid(p.pid, &mut raw, flags) };
+                if ret == 0 {
+                    break;
+                }
+                if ret < 0 {
+                    p.status = Some(0);
+                    break;
+                }
+                p.update(raw);
+                self.notified = false;
+            }
+        }
+    }
+
+    fn describe(&self, mark: char) -> String {
+        let state = match self.state() {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped => "Stopped".to_string(),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(code) => format!("Exit {code}"),
+        };
+        let amp = if self.state() == JobState::Running {
+            " &"
+        } else {
+            ""
+        };
+        format!(
+            "[{}]{}  {:<24}{}{}",
+            self.id, mark, state, self.command, amp
+        )
+    }
+}
+
+impl Process {
+    fn update(&mut self, raw: i32) {
+        if libc::WIFEXITED(raw) {
+            self.status = Some(libc::WEXITSTATUS(raw));
+        } else if libc::WIFSIGNALED(raw) {
+            self.status = Some(128 + libc::WTERMSIG(raw));
+        } else if libc::WIFSTOPPED(raw) {
+            self.stopped = true;
+        } else if libc::WIFCONTINUED(raw) {
+            self.stopped = false;
+        }
+    }
+}
+
+/// ジョブテーブル。末尾ほど新しく、末尾が「カレントジョブ」(+)
+#[derive(Default)]
+pub struct Jobs {
+    list: Vec<Job>,
+}
+
+impl Jobs {
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// 番号を振って登録する（登録時の状態は呼び出し側が知らせる）
+    pub fn add(&mut self, mut job: Job) -> usize {
+        job.notified = true;
+        if job.id == 0 {
+            job.id = self.list.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        self.list.push(job);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let idx = self.list.iter().position(|j| j.id == id)?;
+        Some(self.list.remove(idx))
+    }
+
+    /// `%1` `%%` `%+` `%-` `1` や PID からジョブ番号を探す。None はカレントジョブ
+    pub fn find(&self, spec: Option<&str>) -> std::result::Result<usize, String> {
+        let job = match spec {
+            None | Some("%%" | "%+" | "%") => self.list.last(),
+            Some("%-") => self.list.iter().rev().nth(1),
+            Some(s) if s.starts_with('%') => s[1..]
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| self.list.iter().find(|j| j.id == n)),
+            Some(s) => s
+                .parse::<i32>()
+                .ok()
+                .and_then(|pid| self.list.iter().find(|j| j.pids().any(|p| p == pid))),
+        };
+        match (job, spec) {
+            (Some(job), _) => Ok(job.id),
+            (None, None) => Err("no current job".to_string()),
+            (None, Some(s)) => Err(format!("{s}: no such job")),
+        }
+    }
+
+    /// フォアグラウンドで待ち、各プロセスの終了コードを返す。
+    /// 停止したらテーブルへ登録して知らせ、None を返す
+    pub fn foreground(&mut self, mut job: Job, job_control: bool) -> Option<Vec<i32>> {
+        if job_control && job.pgid > 0 {
+            give_terminal(job.pgid);
+        }
+        job.wait();
+        if job_control {
+            take_terminal();
+        }
+        if job.state() != JobState::Stopped {
+            return Some(job.procs.iter().map(|p| p.status.unwrap_or(0)).collect());
+        }
+        let id = self.add(job);
+        if let Some(job) = self.list.iter().find(|j| j.id == id) {
+            eprintln!("\n{}", job.describe('+'));
+        }
+        None
+    }
+
+    /// 各ジョブの状態変化を拾う
+    pub fn reap(&mut self) {
+        for job in &mut self.list {
+            job.poll();
+        }
+    }
+
+    /// 前回から状態が変わったジョブの通知。終了したジョブはテーブルから外す
+    pub fn notifications(&mut self) -> Vec<String> {
+        self.reap();
+        let mut lines = Vec::new();
+        for (job, mark) in self.with_marks() {
+            if !job.notified && job.state() != JobState::Running {
+                lines.push(job.describe(mark));
+            }
+        }
+        for job in &mut self.list {
+            job.notified = true;
+        }
+        self.list
+            .retain(|j| !matches!(j.state(), JobState::Done(_)));
+        lines
+    }
+
+    /// `jobs` 用の一覧（終了したジョブは表示後に外す）
+    pub fn listing(&mut self, with_pid: bool) -> Vec<String> {
+        self.reap();
+        let lines = self
+            .with_marks()
+            .map(|(job, mark)| {
+                let line = job.describe(mark);
+                if with_pid {
+                    let pids: Vec<String> = job.pids().map(|p| p.to_string()).collect();
+                    format!("{} ({})", line, pids.join(" "))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        for job in &mut self.list {
+            job.notified = true;
+        }
+        self.list
+            .retain(|j| !matches!(j.state(), JobState::Done(_)));
+        lines
+    }
+
+    /// まだ終わっていない（実行中か停止中の）ジョブの数
+    pub fn active_count(&mut self) -> usize {
+        self.reap();
+        self.list
+            .iter()
+            .filter(|j| !matches!(j.state(), JobState::Done(_)))
+            .count()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.list.iter().find(|j| j.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.list.iter_mut().find(|j| j.id == id)
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.list.iter().map(|j| j.id).collect()
+    }
+
+    fn with_marks(&self) -> impl Iterator<Item = (&Job, char)> {
+        let len = self.list.len();
+        self.list.iter().enumerate().map(move |(i, job)| {
+            let mark = match len - i {
+                1 => '+',
+                2 => '-',
+                _ => ' ',
+            };
+            (job, mark)
+        })
+    }
+}
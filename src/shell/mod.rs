@@ -1,16 +1,26 @@
 pub mod builtins;
 pub mod completion;
-mod exe_list;
+pub mod exe_list;
 mod expansion;
 pub mod history;
+pub mod jobs;
+mod universal;
+pub mod variables;
 
-use std::{collections::BTreeMap, env, path::PathBuf};
+use std::{
+    env,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+};
 
 use crate::shell::expansion::{Abbrs, Aliases};
 
 use completion::CompletionStore;
 use exe_list::ExeList;
 use history::History;
+use jobs::Jobs;
+use universal::Universal;
+use variables::{Scope, Variables};
 
 pub struct Shell {
     pub history: History,
@@ -18,35 +28,166 @@ pub struct Shell {
     pub aliases: Aliases,
     pub exe_list: ExeList,
     pub completion: CompletionStore,
-    pub variables: BTreeMap<String, String>,
+    pub variables: Variables,
     pub dir_stack: Vec<PathBuf>,
-    pub exit_requested: bool,
+    pub oldpwd: Option<PathBuf>, // 直前にいたディレクトリ（$OLDPWD。cd と popd で変わる）
+    pub exit_requested: Option<i32>, // exit されたときの終了コード
+    pub exit_warned: bool,       // ジョブが残っている exit を 1 度止めた
+    pub exit_hooks: Vec<Vec<String>>, // 終了時に実行するコマンドの argv（on_exit で登録）
+    pub not_found_handler: Option<Vec<String>>, // コマンドが見つからないときに実行するコマンドの argv（command_not_found で登録）
+    pub pid: u32,                               // シェル本体の PID（$$。サブシェルでも変わらない）
+    pub status: i32,                            // 直前のパイプラインの終了コード（$status / $?）
+    pub pipestatus: Vec<i32>, // 直前のパイプラインの各段の終了コード（$pipestatus）
+    pub options: ShellOptions,
+    pub jobs: Jobs,
+    pub proc_substs: Vec<(OwnedFd, i32)>, // プロセス置換の (親側の fd, pid)。パイプラインの終わりに閉じて回収する
+    pub job_control: bool,                // 対話シェル本体のみ true（fork した子では false）
+    pub interactive: bool, // 行エディタで対話している（-c やスクリプトの実行では false）
+    pub args: Vec<String>, // 位置パラメータ。[0] は $0（シェルかスクリプトの名前）
+}
+
+/// `set -o` で切り替えるオプション
+#[derive(Default)]
+pub struct ShellOptions {
+    pub pipefail: bool, // パイプラインの終了コードを最後に失敗した段のものにする
+    pub dotglob: bool,  // グロブでドットファイルにも一致させる
+    pub nomatch: NoMatch,
+}
+
+/// グロブが何にも一致しなかったときの扱い（`set -o nomatch <mode>`）
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum NoMatch {
+    Error, // fish 風: エラーにしてコマンドを実行しない
+    #[default]
+    Passthrough, // bash 風: パターンをそのまま渡す
+    Null,  // zsh の nullglob 風: 引数ごと取り除く
+}
+
+impl NoMatch {
+    pub fn name(self) -> &'static str {
+        match self {
+            NoMatch::Error => "error",
+            NoMatch::Passthrough => "passthrough",
+            NoMatch::Null => "null",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(NoMatch::Error),
+            "passthrough" => Some(NoMatch::Passthrough),
+            "null" => Some(NoMatch::Null),
+            _ => None,
+        }
+    }
 }
 
 impl Shell {
-    pub fn new() -> Self {
-        init_env();
+    /// interactive でなければ rc を読まず、シグナルや端末もそのままにする
+    pub fn new(interactive: bool) -> Self {
+        init_env(interactive);
+        let history = History::load();
+        // ユニバーサル変数は履歴と同じディレクトリに置く
+        let universal =
+            Universal::load(Path::new(history.path()).with_file_name(".my_shell_universal"));
         let mut s = Self {
-            history: History::load(),
+            history,
             abbrs: Abbrs::new("abbr".into()),
             aliases: Aliases::new("aliases".into()),
             exe_list: ExeList::new(),
             completion: CompletionStore::load().unwrap(),
-            variables: BTreeMap::new(),
+            variables: Variables::from_env(universal),
             dir_stack: Vec::new(),
-            exit_requested: false,
+            oldpwd: None,
+            exit_requested: None,
+            exit_warned: false,
+            exit_hooks: Vec::new(),
+            not_found_handler: None,
+            pid: std::process::id(),
+            status: 0,
+            pipestatus: Vec::new(),
+            options: ShellOptions::default(),
+            jobs: Jobs::default(),
+            proc_substs: Vec::new(),
+            job_control: interactive && jobs::init_job_control(),
+            interactive,
+            args: env::args().take(1).collect(),
         };
-        let rc_path = get_rc_path();
-        s.source(rc_path);
+        // 子プロセスには 1 つ深くなったことを伝える
+        let shlvl = s
+            .variables
+            .get("SHLVL")
+            .and_then(|v| v.parse::<i32>().ok())
+            .unwrap_or(0)
+            + 1;
+        s.variables
+            .set_in(Scope::Global, "SHLVL", shlvl.to_string(), Some(true));
+        if interactive {
+            let rc_path = get_rc_path();
+            s.source(rc_path);
+        }
         s
     }
     fn source(&mut self, path: String) -> i32 {
-        let ret = crate::shell::builtins::source_with_io(&[path], self);
-        print!("{}", ret.stdout);
-        ret.code
+        crate::shell::builtins::source_with_io(&[path], self, &mut builtins::Streams::std())
+    }
+    fn request_exit(&mut self, code: i32) {
+        self.exit_requested = Some(code);
     }
-    fn request_exit(&mut self) {
-        self.exit_requested = true;
+
+    /// cd / popd で prev から移ったあとに呼ぶ。$OLDPWD と、子プロセスに渡す PWD / OLDPWD を更新する
+    pub fn changed_dir(&mut self, prev: PathBuf) {
+        if let Ok(dir) = env::current_dir() {
+            let dir = dir.display().to_string();
+            self.variables.set_in(Scope::Global, "PWD", dir, Some(true));
+        }
+        let old = prev.display().to_string();
+        self.variables
+            .set_in(Scope::Global, "OLDPWD", old, Some(true));
+        self.oldpwd = Some(prev);
+    }
+
+    /// on_exit で登録したコマンドを順に実行する。exit の後でも実行されるよう、終了の要求はいったん外す
+    fn run_exit_hooks(&mut self) {
+        let requested = self.exit_requested.take();
+        for hook in std::mem::take(&mut self.exit_hooks) {
+            if let Err(e) = crate::execute_argv(&hook, self) {
+                eprintln!("on_exit: {}", e);
+            }
+        }
+        self.exit_requested = requested;
+    }
+    /// 実行できなかったコマンドを報告する。見つからないときは登録したハンドラがあれば任せる。
+    /// ハンドラの argv の後ろにコマンド名と引数をつなげて実行する。終了コードはハンドラによらず code
+    pub fn command_not_found(
+        &mut self,
+        name: &str,
+        args: &[String],
+        code: i32,
+        reason: &str,
+    ) -> i32 {
+        if code == 127
+            && let Some(handler) = self.not_found_handler.clone()
+        {
+            let argv: Vec<String> = handler
+                .into_iter()
+                .chain([name.to_string()])
+                .chain(args.iter().cloned())
+                .collect();
+            if let Err(e) = crate::execute_argv(&argv, self) {
+                eprintln!("command_not_found: {}", e);
+            }
+            return code;
+        }
+        eprintln!("{}: {}", name, reason);
+        if code == 127 && !name.contains('/') {
+            let path = self.variables.get("PATH").unwrap_or_default();
+            let near = self.exe_list.suggestions(name, path);
+            if !near.is_empty() {
+                eprintln!("Did you mean: {}?", near.join(", "));
+            }
+        }
+        code
     }
     pub fn get_ghost(&self, buffer: &str) -> String {
         self.history.get_ghost(buffer)
@@ -58,14 +199,9 @@ fn get_rc_path() -> String {
         .unwrap_or_else(|_| env::var("HOME").expect("HOME not set") + "/" + ".my_shell_rc")
 }
 
-fn init_env() {
-    let shlvl = env::var("SHLVL")
-        .ok()
-        .and_then(|s| s.parse::<i32>().ok())
-        .unwrap_or(0)
-        + 1;
-    unsafe {
-        env::set_var("SHLVL", shlvl.to_string());
+fn init_env(interactive: bool) {
+    if !interactive {
+        return;
     }
     unsafe {
         libc::signal(libc::SIGINT, libc::SIG_IGN);
@@ -78,6 +214,11 @@ fn init_env() {
 
 impl Drop for Shell {
     fn drop(&mut self) {
+        self.run_exit_hooks();
+        // 対話していない shell が古い内容で上書きしないように
+        if !self.interactive {
+            return;
+        }
         let _ = self.history.save();
         let _ = self.completion.save();
     }
@@ -0,0 +1,181 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// ファイルが書き換わったかの目印。更新時刻は粗い（同じ刻みの間の書き込みは区別できない）ので、
+/// rename のたびに替わる inode とサイズも見る
+type Stamp = (SystemTime, u64, u64);
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let m = fs::metadata(path).ok()?;
+    Some((m.modified().ok()?, m.ino(), m.len()))
+}
+
+/// 起動中のすべてのシェルで共有する変数（`set -U`）。履歴の隣のファイルに保存する
+pub struct Universal {
+    path: PathBuf,
+    stamp: Option<Stamp>, // 最後に読み書きしたときのファイルの状態
+    pub(super) vars: BTreeMap<String, (String, bool)>, // 名前 → (値, エクスポートするか)
+}
+
+impl Universal {
+    pub(super) fn load(path: PathBuf) -> Self {
+        let mut s = Self {
+            path,
+            stamp: None,
+            vars: BTreeMap::new(),
+        };
+        s.refresh();
+        s
+    }
+
+    /// ほかのシェルがファイルを書き換えていたら読み直す
+    pub(super) fn refresh(&mut self) {
+        let stamp = stamp(&self.path);
+        if stamp == self.stamp {
+            return;
+        }
+        self.stamp = stamp;
+        self.vars = fs::read_to_string(&self.path)
+            .map(|text| parse(&text))
+            .unwrap_or_default();
+    }
+
+    /// ロックを取って最新の内容を読み、edit で書き換えてから一時ファイルの rename で置き換える。
+    /// ほかのシェルが同時に書いても、互いの変更は失われない
+    pub(super) fn update<T>(
+        &mut self,
+        edit: impl FnOnce(&mut BTreeMap<String, (String, bool)>) -> T,
+    ) -> io::Result<T> {
+        // rename でファイルの実体が替わるので、ロックは別のファイルで取る（lock を閉じると外れる）
+        let lock = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.with_extension("lock"))?;
+        if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.stamp = None;
+        self.refresh();
+        let ret = edit(&mut self.vars);
+
+        let tmp = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        let write = || -> io::Result<()> {
+            let mut f = File::create(&tmp)?;
+            f.write_all(format(&self.vars).as_bytes())?;
+            f.sync_all()?;
+            fs::rename(&tmp, &self.path)
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        self.stamp = stamp(&self.path);
+        Ok(ret)
+    }
+}
+
+/// 1 行に 1 変数。`name=value` か `export name=value`（値の \ と改行はエスケープする）
+fn format(vars: &BTreeMap<String, (String, bool)>) -> String {
+    let mut text = String::from("# my_shell universal variables (set -U)\n");
+    for (name, (value, exported)) in vars {
+        if *exported {
+            text += "export ";
+        }
+        let value = value.replace('\\', "\\\\").replace('\n', "\\n");
+        text += &format!("{name}={value}\n");
+    }
+    text
+}
+
+fn parse(text: &str) -> BTreeMap<String, (String, bool)> {
+    let mut vars = BTreeMap::new();
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let (line, exported) = match line.strip_prefix("export ") {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        let Some((name, escaped)) = line.split_once('=') else {
+            continue;
+        };
+        let mut value = String::new();
+        let mut chars = escaped.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => value.push('\n'),
+                Some(c) => value.push(c),
+                None => value.push('\\'),
+            }
+        }
+        vars.insert(name.to_string(), (value, exported));
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(items: &[(&str, &str, bool)]) -> BTreeMap<String, (String, bool)> {
+        items
+            .iter()
+            .map(|(name, value, exported)| (name.to_string(), (value.to_string(), *exported)))
+            .collect()
+    }
+
+    #[test]
+    fn format_and_parse_round_trip() {
+        let original = vars(&[
+            ("plain", "value", false),
+            ("exported", "with space", true),
+            ("multi", "line1\nline2", false),
+            ("backslash", "a\\nb\\", true),
+            ("equals", "a=b", false),
+            ("empty", "", false),
+        ]);
+        let text = format(&original);
+        assert!(text.starts_with("# my_shell universal variables (set -U)\n"));
+        assert_eq!(text.lines().count(), original.len() + 1);
+        assert_eq!(parse(&text), original);
+    }
+
+    #[test]
+    fn refresh_picks_up_writes_from_other_shells() {
+        let path =
+            std::env::temp_dir().join(format!("my_shell_universal_test_{}", std::process::id()));
+        let mut one = Universal::load(path.clone());
+        let mut two = Universal::load(path.clone());
+        one.update(|vars| vars.insert("a".into(), ("1".into(), false)))
+            .unwrap();
+        two.update(|vars| vars.insert("b".into(), ("2".into(), true)))
+            .unwrap();
+        one.refresh();
+        assert_eq!(one.vars, vars(&[("a", "1", false), ("b", "2", true)]));
+        two.update(|vars| vars.remove("a")).unwrap();
+        one.refresh();
+        assert_eq!(one.vars, vars(&[("b", "2", true)]));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("lock"));
+    }
+
+    #[test]
+    fn parse_skips_comments_and_broken_lines() {
+        let text = "# comment\nexport a=1\nno_equals\nb=x\\ny\n";
+        assert_eq!(parse(text), vars(&[("a", "1", true), ("b", "x\ny", false)]));
+    }
+}
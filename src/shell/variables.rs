@@ -0,0 +1,178 @@
+use std::{collections::BTreeMap, env, io};
+
+use super::universal::Universal;
+
+/// `set -l` / `set -g` で選ぶスコープ
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Local,  // 実行中の source のファイルの中だけ（source の外ではグローバルと同じ）
+    Global, // シェル全体
+}
+
+#[derive(Clone)]
+struct Variable {
+    value: String,
+    exported: bool, // 子プロセスの環境変数として渡す
+}
+
+/// シェル変数。source するたびにローカルのスコープを 1 段積み、内側から順に引く。
+/// どのスコープにも無ければユニバーサル変数（ほかのシェルと共有）を引く
+pub struct Variables {
+    scopes: Vec<BTreeMap<String, Variable>>, // [0] がグローバル
+    universal: Universal,
+}
+
+impl Variables {
+    /// 起動時の環境変数はエクスポート済みのグローバル変数にする
+    pub fn from_env(universal: Universal) -> Self {
+        let global = env::vars()
+            .map(|(name, value)| {
+                let var = Variable {
+                    value,
+                    exported: true,
+                };
+                (name, var)
+            })
+            .collect();
+        Self {
+            scopes: vec![global],
+            universal,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match self.lookup(name) {
+            Some(var) => Some(&var.value),
+            None => self
+                .universal
+                .vars
+                .get(name)
+                .map(|(value, _)| value.as_str()),
+        }
+    }
+
+    /// 既にある変数はそのスコープで書き換え、なければグローバルに作る。エクスポートの有無は変えない。
+    /// ユニバーサル変数はグローバル変数で隠すだけで、書き換えるのは set_universal だけ
+    pub fn set(&mut self, name: &str, value: String) {
+        match self.lookup_mut(name) {
+            Some(var) => var.value = value,
+            None => {
+                let var = Variable {
+                    value,
+                    exported: false,
+                };
+                self.scopes[0].insert(name.to_string(), var);
+            }
+        }
+    }
+
+    /// スコープを指定して入れる。export が None ならそのスコープでのエクスポートの有無を引き継ぐ
+    pub fn set_in(&mut self, scope: Scope, name: &str, value: String, export: Option<bool>) {
+        let map = match scope {
+            Scope::Local => self.scopes.last_mut().unwrap(),
+            Scope::Global => &mut self.scopes[0],
+        };
+        let exported = export.unwrap_or_else(|| map.get(name).is_some_and(|var| var.exported));
+        map.insert(name.to_string(), Variable { value, exported });
+    }
+
+    /// 既にある変数のエクスポートを切り替える。なければ（ユニバーサル変数の値か）空の値でグローバルに作る
+    pub fn set_exported(&mut self, name: &str, exported: bool) {
+        match self.lookup_mut(name) {
+            Some(var) => var.exported = exported,
+            None => {
+                let value = self.get(name).unwrap_or_default().to_string();
+                self.set_in(Scope::Global, name, value, Some(exported))
+            }
+        }
+    }
+
+    /// 一番内側の name を消す。なければ false
+    pub fn erase(&mut self, name: &str) -> bool {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.remove(name))
+            .is_some()
+    }
+
+    /// ユニバーサル変数を書き、ファイルに保存する。export が None ならエクスポートの有無を引き継ぐ
+    pub fn set_universal(
+        &mut self,
+        name: &str,
+        value: String,
+        export: Option<bool>,
+    ) -> io::Result<()> {
+        self.universal.update(|vars| {
+            let exported = export.unwrap_or_else(|| vars.get(name).is_some_and(|(_, e)| *e));
+            vars.insert(name.to_string(), (value, exported));
+        })
+    }
+
+    /// ユニバーサル変数を消し、ファイルに保存する。なければ false
+    pub fn erase_universal(&mut self, name: &str) -> io::Result<bool> {
+        self.universal.update(|vars| vars.remove(name).is_some())
+    }
+
+    /// ほかのシェルが書き換えたユニバーサル変数を読み直す（プロンプトを出す前に呼ぶ）
+    pub fn refresh_universal(&mut self) {
+        self.universal.refresh();
+    }
+
+    /// ユニバーサル変数を名前順に (名前, 値, エクスポートするか) で返す
+    pub fn list_universal(&self) -> Vec<(&str, &str, bool)> {
+        self.universal
+            .vars
+            .iter()
+            .map(|(name, (value, exported))| (name.as_str(), value.as_str(), *exported))
+            .collect()
+    }
+
+    /// source の開始。以後 Scope::Local の変数はここに入る
+    pub fn push_scope(&mut self) {
+        self.scopes.push(BTreeMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// 見えている変数を名前順に (名前, 値, エクスポートするか) で返す（内側で隠れたものは除く）
+    pub fn list(&self) -> Vec<(&str, &str, bool)> {
+        let mut visible: BTreeMap<&str, (&str, bool)> = BTreeMap::new();
+        for (name, value, exported) in self.list_universal() {
+            visible.insert(name, (value, exported));
+        }
+        for scope in &self.scopes {
+            for (name, var) in scope {
+                visible.insert(name, (&var.value, var.exported));
+            }
+        }
+        visible
+            .into_iter()
+            .map(|(name, (value, exported))| (name, value, exported))
+            .collect()
+    }
+
+    /// 子プロセスに渡す環境変数
+    pub fn exported(&self) -> Vec<(&str, &str)> {
+        self.list()
+            .into_iter()
+            .filter(|(_, _, exported)| *exported)
+            .map(|(name, value, _)| (name, value))
+            .collect()
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+}
@@ -8,7 +8,6 @@ use crate::ui::term::ansi;
 use crate::ui::term::ansi::cursor_right;
 use crate::ui::term::ansi::cursor_to_line_start;
 use crate::ui::term::ansi::cursor_up;
-use crate::ui::term::ansi::delete_buffer;
 use crate::ui::term::ansi::newline;
 use crate::ui::term::color::Color;
 use crate::ui::term::color::bg;
@@ -35,6 +34,26 @@ pub fn print_prompt() {
     write!(stdout().lock(), "{}", term::prompt::get_prompt()).unwrap();
 }
 
+/// 構文エラーの位置をコマンドラインの下に ^ で示す（line は 1 始まり、column は行頭からの文字数）
+pub fn print_error_caret(line: usize, column: usize, message: &str) {
+    let width = (read_terminal_size().width as usize).max(1);
+    // 2 行目以降は継続プロンプトの分だけ右にある
+    let column = if line > 1 {
+        column + CONTINUATION_PROMPT.len()
+    } else {
+        column
+    };
+    write!(
+        stdout().lock(),
+        "{}{}^ {}{}",
+        " ".repeat(column % width),
+        fg(Color::Red),
+        message,
+        fg(Color::Reset)
+    )
+    .unwrap();
+}
+
 pub fn print_hat_c() {
     write!(
         stdout().lock(),
@@ -45,36 +64,70 @@ pub fn print_hat_c() {
     .unwrap();
 }
 
+/// 複数行の入力で 2 行目以降の行頭に出す
+const CONTINUATION_PROMPT: &str = "> ";
+
+/// text[..offset] を表示し終えた位置（表示の先頭からの行, 桁）。折り返しと継続プロンプトも数える
+fn layout(text: &str, offset: usize, width: usize) -> (usize, usize) {
+    let mut row = 0;
+    let mut len: usize = 0;
+    for (i, line) in text[..offset].split('\n').enumerate() {
+        if i > 0 {
+            row += len.saturating_sub(1) / width + 1;
+            len = CONTINUATION_PROMPT.len();
+        }
+        len += line.len();
+    }
+    (row + len / width, len % width)
+}
+
 pub fn print_command_line(buffer: &str, cursor: usize, ghost: &str) {
     // format = "{buffer}{gray_color}{ghost}{reset_color}"
-    let width = read_terminal_size().width;
+    // buffer の改行ごとに "{newline}{gray_color}> {reset_color}" を挟む
+    let width = read_terminal_size().width as usize;
+    let gray = fg(Color::BrightBlack);
+    let reset = fg(Color::Reset);
     let mut out = stdout().lock();
-    write!(out, "{}", buffer).unwrap();
-    let ghost_len = ghost.len();
+    let continuation = format!("{}{gray}{CONTINUATION_PROMPT}{reset}", newline());
+    write!(out, "{}", buffer.replace('\n', &continuation)).unwrap();
+    // 複数行の履歴は 1 行目だけ見せる
+    let ghost = ghost.split('\n').next().unwrap_or("");
     if !ghost.is_empty() {
-        let gray = fg(Color::BrightBlack);
-        let reset = fg(Color::Reset);
         write!(out, "{gray}{ghost}{reset}",).unwrap();
     }
-    let display_len = buffer.len() + ghost_len;
-    if display_len.is_multiple_of(width as usize) && display_len != 0 {
+    let text = buffer.to_string() + ghost;
+    let (end_row, end_col) = layout(&text, text.len(), width);
+    if end_col == 0 && !text.is_empty() {
         write!(out, "{}", ansi::scroll_up(1)).unwrap();
     }
-    write!(out, "{}", ansi::cursor_back(display_len)).unwrap();
-    write!(out, "{}", ansi::cursor_down(cursor as u32 / width as u32)).unwrap();
-    write!(out, "{}", ansi::cursor_right(cursor as u32 % width as u32)).unwrap();
+    let (row, col) = layout(&text, cursor, width);
+    write!(out, "{}", cursor_up(end_row as u32)).unwrap();
+    write!(out, "{}", cursor_to_line_start()).unwrap();
+    write!(out, "{}", ansi::cursor_down(row as u32)).unwrap();
+    write!(out, "{}", ansi::cursor_right(col as u32)).unwrap();
 }
 
 pub fn clean_term() {
     write!(stdout().lock(), "{}", ansi::clear()).unwrap();
 }
 
-pub fn delete_printing(cursor: usize) {
-    write!(stdout().lock(), "{}", delete_buffer(cursor)).unwrap();
+/// print_command_line で出したものを消して、表示の先頭に戻る
+pub fn delete_printing(buffer: &str, cursor: usize) {
+    let width = read_terminal_size().width as usize;
+    let (row, _) = layout(buffer, cursor, width);
+    write!(
+        stdout().lock(),
+        "{}{}{}",
+        cursor_up(row as u32),
+        cursor_to_line_start(),
+        ansi::delete_after()
+    )
+    .unwrap();
 }
 
 pub fn print_candidates(
     candidates: &Vec<String>,
+    buffer: &str,
     cursor: usize,
     index: Option<usize>,
     fixed_len: usize,
@@ -84,6 +137,7 @@ pub fn print_candidates(
     }
     let size = read_terminal_size();
     let (term_height, term_width) = (size.height as usize, size.width as usize);
+    let (_, column) = layout(buffer, cursor, term_width);
 
     let mut o_width = 1;
     let mut o_max_lens = vec![candidates.iter().map(|line| line.len()).max().unwrap()];
@@ -113,7 +167,7 @@ pub fn print_candidates(
 
     if o_height > term_height {
         let buffer = "Too many candidates, can't output";
-        print_buffer_and_back(buffer, cursor);
+        print_buffer_and_back(buffer, column);
         return 0;
     }
 
@@ -150,15 +204,14 @@ pub fn print_candidates(
         }
     }
 
-    print_buffer_and_back(&buffer, cursor);
+    print_buffer_and_back(&buffer, column);
     return o_width;
 }
 
-fn print_buffer_and_back(buffer: &str, cursor: usize) {
-    let width = read_terminal_size().width as usize;
+fn print_buffer_and_back(buffer: &str, column: usize) {
     let newline = &newline();
     let up = cursor_up(buffer.matches("\r\n").count() as u32 + 1);
     let left_end = cursor_to_line_start();
-    let right = cursor_right((cursor % width) as u32);
+    let right = cursor_right(column as u32);
     write!(stdout().lock(), "{newline}{buffer}{up}{left_end}{right}").unwrap();
 }